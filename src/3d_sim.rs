@@ -1,29 +1,101 @@
+mod diagnostics;
+mod export;
+mod integrator;
 mod kernel;
 mod model;
+mod profiler;
+mod real;
 mod render;
+mod scene;
 mod simulator;
+mod time_step;
 mod util_3d;
 
-use render::Render;
+use kernel::CubicSpline;
+use macroquad::input::{is_key_pressed, KeyCode};
+use render::{ColorMode, Render};
+use scene::Scene;
 use simulator::Simulator;
-use util_3d::*;
 
 #[macroquad::main("simulation")]
 async fn main() {
-    let mut sim = Simulator::setup();
+    let args = std::env::args().collect::<Vec<_>>();
+    let value_of = |flag: &str| {
+        args.iter()
+            .position(|a| a == flag)
+            .and_then(|i| args.get(i + 1).cloned())
+    };
+
+    let mut sim = Simulator::new(Scene::from_args(&args));
+
+    // `--trace <path>` record every step, press T to write a Chrome trace
+    let trace_path = value_of("--trace");
+    let mut hint = vec!["press C to change colour".to_string()];
+    if trace_path.is_some() {
+        sim.start_trace();
+        hint.push("press T to save trace".to_string());
+    }
+    // `--export <prefix>` press E to write every particle to <prefix>_<n>.csv
+    let export_prefix = value_of("--export");
+    let mut export_count = 0;
+    if export_prefix.is_some() {
+        hint.push("press E to export particles".to_string());
+    }
 
     let mut render = Render::new();
+    // `--color <name>` distance, pressure, viscosity, vorticity, material or temperature
+    if let Some(name) = value_of("--color") {
+        render.set_color_mode(
+            ColorMode::from_name(&name).unwrap_or_else(|| panic!("unknown colour {name}")),
+        );
+    }
     let frame_period = ((1. / 2.) * 1000.) as u128;
     let mut next_render = std::time::Instant::now();
 
     loop {
         sim.update();
         if next_render.elapsed().as_millis() >= frame_period {
-            dbg!(sim.get_time());
+            if let Some(path) = trace_path.as_ref().filter(|_| is_key_pressed(KeyCode::T)) {
+                let events = sim.take_trace();
+                match profiler::write_chrome_trace(path, &events) {
+                    Ok(_) => println!("trace saved to {path}"),
+                    Err(e) => println!("unable to save trace to {path}: {e}"),
+                }
+            }
+            if let Some(prefix) = export_prefix
+                .as_ref()
+                .filter(|_| is_key_pressed(KeyCode::E))
+            {
+                let path = format!("{prefix}_{export_count}.csv");
+                match export::write_csv(&path, sim.get_space()) {
+                    Ok(_) => println!("particles saved to {path}"),
+                    Err(e) => println!("unable to save particles to {path}: {e}"),
+                }
+                export_count += 1;
+            }
+            if is_key_pressed(KeyCode::C) {
+                render.next_color_mode();
+            }
+
             let space = sim.get_space();
+            let noise = diagnostics::pressure_noise::<CubicSpline>(space);
+            let (h_min, h_max) = diagnostics::kernel_radius_range(space);
+            let neighbours = diagnostics::mean_neighbour_count::<CubicSpline>(space);
+            let status = sim.status().into_iter().chain([
+                format!("colour: {}", render.get_color_mode().name()),
+                format!("pressure noise: {noise:.4}"),
+                format!("h range: {h_min:.3} - {h_max:.3}, {neighbours:.1} neighbours"),
+            ]);
+            let lines = status.chain(hint.iter().cloned()).collect::<Vec<_>>();
             let display_distance = sim.get_display_distance();
             next_render = render
-                .render_distance_from_zero(space, display_distance)
+                .render_distance_from_zero(
+                    space,
+                    sim.get_solid(),
+                    display_distance,
+                    sim.get_step_timing(),
+                    &lines,
+                )
                 .await;
         }
     }
@@ -1,23 +1,338 @@
+mod integrator;
 mod kernel;
 mod model;
+mod profiler;
+mod real;
+mod scene;
 mod simulator;
+mod time_step;
 mod util_3d;
-use crate::simulator::Simulator;
+use std::{
+    env, fs,
+    time::{Duration, Instant},
+};
 
-fn main() {
-    let mut sim = Simulator::setup();
+use itertools::izip;
+use serde::Serialize;
+
+use crate::kernel::CubicSpline;
+use crate::scene::{PairLoop, Scene};
+use crate::simulator::{Phase, Simulator};
+use crate::util_3d::{NeighbourList, Space};
+
+#[derive(Debug)]
+struct Config {
+    particle_per_side: Vec<isize>,
+    threads: Vec<usize>,
+    pair_loops: Vec<PairLoop>,
+    steps: usize,
+    warmup: usize,
+    csv: bool,
+    output: Option<String>,
+    trace: Option<String>,
+    kernel: bool,
+    /// Flags of the viewer, `--particles` and `--pair-loop` override its cube and pair loop.
+    scene: Scene,
+}
+
+impl Config {
+    fn from_args() -> Self {
+        let available = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let mut obj = Self {
+            particle_per_side: vec![8, 10, 12],
+            threads: vec![1, available],
+            pair_loops: vec![PairLoop::Space],
+            steps: 20,
+            warmup: 3,
+            csv: false,
+            output: None,
+            trace: None,
+            kernel: false,
+            scene: Scene::default(),
+        };
+        obj.threads.dedup();
+
+        let mut scene_args = vec![];
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .unwrap_or_else(|| panic!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--particles" => obj.particle_per_side = parse_list(&value()),
+                "--threads" => obj.threads = parse_list(&value()),
+                "--pair-loop" => {
+                    obj.pair_loops = value()
+                        .split(',')
+                        .map(|name| {
+                            PairLoop::from_name(name.trim())
+                                .unwrap_or_else(|| panic!("unknown pair loop {name}"))
+                        })
+                        .collect()
+                }
+                "--steps" => obj.steps = value().parse().expect("--steps"),
+                "--warmup" => obj.warmup = value().parse().expect("--warmup"),
+                "--format" => {
+                    obj.csv = match value().as_str() {
+                        "csv" => true,
+                        "json" => false,
+                        other => panic!("unknown format {other}"),
+                    }
+                }
+                "--output" => obj.output = Some(value()),
+                "--trace" => obj.trace = Some(value()),
+                "--kernel" => obj.kernel = true,
+                "--help" => panic!(
+                    "usage: benchmark [--particles 8,10,12] [--threads 1,4] \
+                     [--pair-loop space,soa,simd] [--steps 20] [--warmup 3] [--format json|csv] \
+                     [--output path] [--trace path] [--kernel] [scene flags of 3d_sim]"
+                ),
+                _ => scene_args.push(arg),
+            }
+        }
+        obj.scene = Scene::from_args(&scene_args);
+        assert!(obj.steps > 0);
+        obj
+    }
+}
 
-    let mut step = 0.1;
+fn parse_list<T: std::str::FromStr>(text: &str) -> Vec<T>
+where
+    T::Err: std::fmt::Debug,
+{
+    text.split(',')
+        .map(|v| v.trim().parse().expect("comma separated list"))
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+struct Record {
+    particles: usize,
+    threads: usize,
+    pair_loop: &'static str,
+    phase: &'static str,
+    samples: usize,
+    median_ms: f64,
+    mean_ms: f64,
+    variance_ms2: f64,
+    min_ms: f64,
+    max_ms: f64,
+    /// Neighbour pairs per second through a pair loop of a model, only with `--kernel`.
+    mpair_per_s: Option<f64>,
+}
+
+impl Record {
+    fn new(
+        particles: usize,
+        threads: usize,
+        pair_loop: PairLoop,
+        phase: &'static str,
+        samples: &[Duration],
+    ) -> Self {
+        let mut ms = samples
+            .iter()
+            .map(|d| d.as_secs_f64() * 1e3)
+            .collect::<Vec<_>>();
+        ms.sort_by(f64::total_cmp);
+
+        let n = ms.len();
+        let median = if n % 2 == 1 {
+            ms[n / 2]
+        } else {
+            (ms[n / 2 - 1] + ms[n / 2]) / 2.
+        };
+        let mean = ms.iter().sum::<f64>() / n as f64;
+        // sample variance, zero for a single sample
+        let variance = if n > 1 {
+            ms.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64
+        } else {
+            0.
+        };
+        Self {
+            particles,
+            threads,
+            pair_loop: pair_loop.name(),
+            phase,
+            samples: n,
+            median_ms: median,
+            mean_ms: mean,
+            variance_ms2: variance,
+            min_ms: ms[0],
+            max_ms: ms[n - 1],
+            mpair_per_s: None,
+        }
+    }
+
+    // `pairs` visited in every sample
+    fn with_pairs(self, pairs: &[usize], samples: &[Duration]) -> Self {
+        let seconds = samples.iter().sum::<Duration>().as_secs_f64();
+        Self {
+            mpair_per_s: Some(pairs.iter().sum::<usize>() as f64 / seconds / 1e6),
+            ..self
+        }
+    }
+
+    const CSV_HEADER: &'static str = "particles,threads,pair_loop,phase,samples,median_ms,mean_ms,\
+        variance_ms2,min_ms,max_ms,mpair_per_s";
+
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            self.particles,
+            self.threads,
+            self.pair_loop,
+            self.phase,
+            self.samples,
+            self.median_ms,
+            self.mean_ms,
+            self.variance_ms2,
+            self.min_ms,
+            self.max_ms,
+            self.mpair_per_s.map_or(String::new(), |m| m.to_string())
+        )
+    }
+}
 
-    loop {
+// Scopes the models open around their pair loops, named after the phase they belong to
+fn pair_scopes(pair_loop: PairLoop) -> [(&'static str, &'static str); 3] {
+    match pair_loop {
+        PairLoop::Space => [
+            ("density_pairs", "Density::update_density"),
+            ("pressure_pairs", "Tait::accelration"),
+            ("viscosity_pairs", "Artificial::accelration"),
+        ],
+        PairLoop::Soa => [
+            ("density_pairs", "Density::density_soa"),
+            ("pressure_pairs", "Tait::accelration_soa"),
+            ("viscosity_pairs", "Artificial::accelration_soa"),
+        ],
+        PairLoop::Simd => [
+            ("density_pairs", "Density::density_simd"),
+            ("pressure_pairs", "Tait::accelration_simd"),
+            ("viscosity_pairs", "Artificial::accelration_simd"),
+        ],
+    }
+}
+
+// Pairs within the support of either particle, the ones every pair loop sums
+fn pair_count(space: &Space) -> usize {
+    let soa = space.to_soa();
+    let neighbours = NeighbourList::new::<CubicSpline>(&soa);
+    (0..soa.len()).map(|i| neighbours.of(i).len()).sum()
+}
+
+fn run(
+    config: &Config,
+    particle_per_side: isize,
+    threads: usize,
+    pair_loop: PairLoop,
+    trace: &mut Vec<profiler::Event>,
+) -> Vec<Record> {
+    let mut sim = Simulator::new(Scene {
+        particle_per_side,
+        pair_loop,
+        ..config.scene.clone()
+    });
+    let particles = sim.get_space().particles().count();
+
+    (0..config.warmup).for_each(|_| sim.update());
+    if config.trace.is_some() {
+        sim.start_trace();
+    }
+
+    let scopes = pair_scopes(pair_loop);
+    let mut phases = vec![vec![]; Phase::ALL.len()];
+    let mut pair_loops = vec![vec![]; scopes.len()];
+    let mut pairs = vec![vec![]; scopes.len()];
+    let mut total = vec![];
+    for _ in 0..config.steps {
+        let start = Instant::now();
         sim.update();
-        let t = sim.get_time();
-        if t >= step {
-            dbg!(t);
-            step += 0.1;
+        total.push(start.elapsed());
+
+        let timing = sim.get_step_timing();
+        Phase::ALL
+            .iter()
+            .zip(phases.iter_mut())
+            .for_each(|(&phase, samples)| samples.push(timing.get(phase)));
+        if config.kernel {
+            // taken after the step, the pairs barely change within one
+            let count = pair_count(sim.get_space());
+            izip!(&scopes, &mut pair_loops, &mut pairs).for_each(
+                |(&(_, scope), samples, pairs)| {
+                    let calls = timing.events().iter().filter(|e| e.name == scope).count();
+                    samples.push(timing.get_by_name(scope));
+                    pairs.push(calls * count);
+                },
+            );
         }
-        if t >= 2. {
-            break;
+    }
+
+    trace.extend(sim.take_trace());
+    sim.status().iter().for_each(|line| eprintln!("  {line}"));
+
+    let record =
+        |phase, samples: &[Duration]| Record::new(particles, threads, pair_loop, phase, samples);
+    let mut records = Phase::ALL
+        .iter()
+        .zip(&phases)
+        .map(|(phase, samples)| record(phase.name(), samples))
+        .collect::<Vec<_>>();
+    records.push(record("step", &total));
+    if config.kernel {
+        records.extend(
+            izip!(&scopes, &pair_loops, &pairs).map(|(&(phase, _), samples, pairs)| {
+                record(phase, samples).with_pairs(pairs, samples)
+            }),
+        );
+    }
+    records
+}
+
+fn csv_table(records: &[Record]) -> String {
+    std::iter::once(Record::CSV_HEADER.to_string())
+        .chain(records.iter().map(Record::to_csv))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn main() {
+    let config = Config::from_args();
+
+    let mut records = vec![];
+    let mut trace = vec![];
+    for &threads in &config.threads {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        for &particle_per_side in &config.particle_per_side {
+            for &pair_loop in &config.pair_loops {
+                eprintln!(
+                    "running {} particles on {threads} threads with the {} pair loop",
+                    particle_per_side.pow(3),
+                    pair_loop.name()
+                );
+                records.extend(
+                    pool.install(|| {
+                        run(&config, particle_per_side, threads, pair_loop, &mut trace)
+                    }),
+                );
+            }
         }
     }
+    if let Some(path) = &config.trace {
+        profiler::write_chrome_trace(path, &trace).unwrap();
+    }
+
+    let text = if config.csv {
+        csv_table(&records)
+    } else {
+        serde_json::to_string_pretty(&records).unwrap()
+    };
+    match &config.output {
+        Some(path) => fs::write(path, text).unwrap(),
+        None => println!("{text}"),
+    }
 }
@@ -0,0 +1,104 @@
+//! Scalar summaries of a `Space`, shown on the HUD and written next to exports.
+
+use crate::kernel::Kernel;
+use crate::real::*;
+use crate::util_3d::*;
+
+/// RMS of `p_a - <p>_a` over the pressure range, `<p>_a` is the Shepard average of the
+/// neighbours. Zero for a smooth field, grows with particle-scale pressure oscillation.
+pub fn pressure_noise<T: Kernel>(space: &Space) -> Real {
+    let (low, high) = space
+        .particles()
+        .map(|p| p.pressure)
+        .fold((Real::INFINITY, -Real::INFINITY), |(l, h), p| {
+            (l.min(p), h.max(p))
+        });
+    let range = high - low;
+    if space.is_empty() || range <= 0. {
+        return 0.;
+    }
+
+    let squared = space
+        .particles()
+        .map(|a| {
+            let kernel = T::new(a.kernel_radius);
+            let (sum, weight) = space
+                .neighbour(a, kernel.support_radius())
+                .map(|b| {
+                    let w = kernel.function(a.position - b.position) * b.mass / b.density;
+                    (b.pressure * w, w)
+                })
+                .fold((0., 0.), |s, v| (s.0 + v.0, s.1 + v.1));
+            (a.pressure - sum / weight).powi(2)
+        })
+        .sum::<Real>();
+    (squared / space.len() as Real).sqrt() / range
+}
+
+/// Smallest and largest kernel radius.
+pub fn kernel_radius_range(space: &Space) -> (Real, Real) {
+    space
+        .particles()
+        .map(|p| p.kernel_radius)
+        .fold((Real::INFINITY, -Real::INFINITY), |(l, h), r| {
+            (l.min(r), h.max(r))
+        })
+}
+
+/// Average number of particles inside a kernel support, the particle itself included.
+pub fn mean_neighbour_count<T: Kernel>(space: &Space) -> Real {
+    if space.is_empty() {
+        return 0.;
+    }
+    let count = space
+        .particles()
+        .map(|a| {
+            let radius = T::new(a.kernel_radius).support_radius();
+            space
+                .neighbour(a, radius)
+                .filter(|b| (a.position - b.position).length() <= radius)
+                .count()
+        })
+        .sum::<usize>();
+    count as Real / space.len() as Real
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::CubicSpline;
+
+    #[test]
+    fn noise_of_checkerboard() {
+        let h = 1.3;
+        let particles = init_setup::create_cube(1., 6, Vector::ZERO, 1., h);
+        let with_pressure = |pressure: &dyn Fn(Vector) -> Real| {
+            let particles = particles
+                .iter()
+                .map(|p| Particle {
+                    density: 1.,
+                    pressure: pressure(p.position),
+                    ..p.clone()
+                })
+                .collect();
+            Space::new(h, particles)
+        };
+
+        let uniform = with_pressure(&|_| 3.);
+        assert_eq!(pressure_noise::<CubicSpline>(&uniform), 0.);
+
+        let linear = pressure_noise::<CubicSpline>(&with_pressure(&|x| x.y));
+        let checkerboard = with_pressure(&|x| ((x.x + x.y + x.z + 10.).round() as i32 % 2) as Real);
+        assert!(pressure_noise::<CubicSpline>(&checkerboard) > 4. * linear);
+    }
+
+    #[test]
+    fn neighbours_of_lattice() {
+        let particles = init_setup::create_cube(1., 3, Vector::ZERO, 1., 0.6);
+        let space = Space::new(0.6, particles);
+        // support 1.2 reaches the 6 face neighbours only
+        let expect = (8. * 4. + 12. * 5. + 6. * 6. + 7.) / 27.;
+        assert!((mean_neighbour_count::<CubicSpline>(&space) - expect).abs() < 1e-5);
+        assert_eq!(kernel_radius_range(&space), (0.6, 0.6));
+    }
+}
@@ -0,0 +1,66 @@
+//! Per-particle fields written to disk for plotting outside the viewer.
+
+use std::{fmt::Write, fs, io, path::Path};
+
+use crate::util_3d::*;
+
+const HEADER: &str =
+    "id,x,y,z,vx,vy,vz,mass,kernel_radius,density,pressure,viscosity,wx,wy,wz,material,temperature,liquid_fraction";
+
+/// One row per particle, sorted by id.
+pub fn to_csv(space: &Space) -> String {
+    let mut particles = space.particles().collect::<Vec<_>>();
+    particles.sort_by_key(|p| p.id);
+
+    let mut csv = format!("{HEADER}\n");
+    particles.into_iter().for_each(|p| {
+        let (x, v) = (p.position, p.velocity);
+        writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            p.id,
+            x.x,
+            x.y,
+            x.z,
+            v.x,
+            v.y,
+            v.z,
+            p.mass,
+            p.kernel_radius,
+            p.density,
+            p.pressure,
+            p.viscosity,
+            p.vorticity.x,
+            p.vorticity.y,
+            p.vorticity.z,
+            p.material,
+            p.temperature,
+            p.liquid_fraction
+        )
+        .unwrap();
+    });
+    csv
+}
+
+pub fn write_csv(path: impl AsRef<Path>, space: &Space) -> io::Result<()> {
+    fs::write(path, to_csv(space))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::real::Vector;
+
+    #[test]
+    fn row_per_particle() {
+        let space = Space::new(1., init_setup::create_cube(1., 3, Vector::ZERO, 1., 1.));
+        let csv = to_csv(&space);
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 28);
+        assert_eq!(lines[0], HEADER);
+        assert!(lines[1].starts_with("0,-1,-1,-1,"));
+        assert!(lines
+            .iter()
+            .all(|l| l.split(',').count() == HEADER.split(',').count()));
+    }
+}
@@ -0,0 +1,244 @@
+use std::ops::{Add, Mul};
+
+use crate::real::*;
+use itertools::izip;
+
+/// State advanced by an `Integrator`, every `Vec` is indexed by particle id.
+pub trait System {
+    fn position(&self) -> Vec<Vector>;
+    fn velocity(&self) -> Vec<Vector>;
+    fn set_state(&mut self, position: &[Vector], velocity: &[Vector]);
+    /// Evaluate every force at the current state.
+    fn acceleration(&mut self) -> Vec<Vector>;
+    /// Velocity the positions move with, `velocity` itself unless the system smooths it (XSPH).
+    fn drift_velocity(&self, velocity: &[Vector]) -> Vec<Vector> {
+        velocity.to_vec()
+    }
+
+    /// Density when it is integrated in time, empty when it follows from the positions.
+    fn density(&self) -> Vec<Real> {
+        vec![]
+    }
+    fn set_density(&mut self, _density: &[Real]) {}
+    /// `d density / dt` found by the last `acceleration` call, same length as `density`.
+    fn density_rate(&self) -> Vec<Real> {
+        vec![]
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    /// First order, kick then drift, one force evaluation per step.
+    SymplecticEuler,
+    /// Second order kick-drift-kick leapfrog, the forces at the end of a step are reused by the next one.
+    #[default]
+    VelocityVerlet,
+    /// Second order midpoint predictor-corrector (Monaghan 1989), two force evaluations per step.
+    PredictorCorrector,
+    /// Classic fourth order Runge-Kutta, four force evaluations per step.
+    RungeKutta4,
+}
+
+impl Integrator {
+    pub const ALL: [Integrator; 4] = [
+        Integrator::SymplecticEuler,
+        Integrator::VelocityVerlet,
+        Integrator::PredictorCorrector,
+        Integrator::RungeKutta4,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Integrator::SymplecticEuler => "symplectic_euler",
+            Integrator::VelocityVerlet => "velocity_verlet",
+            Integrator::PredictorCorrector => "predictor_corrector",
+            Integrator::RungeKutta4 => "rk4",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|i| i.name() == name)
+    }
+
+    /// Order of the global error, halving `dt` divides it by `2^order`.
+    #[cfg(test)]
+    pub fn order(&self) -> i32 {
+        match self {
+            Integrator::SymplecticEuler => 1,
+            Integrator::VelocityVerlet | Integrator::PredictorCorrector => 2,
+            Integrator::RungeKutta4 => 4,
+        }
+    }
+
+    /// Advance `system` by `dt`, `acceleration` and `density_rate` are at the current state.
+    /// Return the acceleration at the new state when the scheme already computed it.
+    pub fn step(
+        &self,
+        system: &mut impl System,
+        acceleration: &[Vector],
+        dt: Real,
+    ) -> Option<Vec<Vector>> {
+        let x0 = system.position();
+        let v0 = system.velocity();
+        let rho0 = system.density();
+        let r0 = system.density_rate();
+        match self {
+            Integrator::SymplecticEuler => {
+                let v1 = axpy(&v0, acceleration, dt);
+                let x1 = axpy(&x0, &system.drift_velocity(&v1), dt);
+                system.set_density(&axpy(&rho0, &r0, dt));
+                system.set_state(&x1, &v1);
+                None
+            }
+            Integrator::VelocityVerlet => {
+                let v_half = axpy(&v0, acceleration, dt / 2.);
+                let x1 = axpy(&x0, &system.drift_velocity(&v_half), dt);
+                let rho_half = axpy(&rho0, &r0, dt / 2.);
+                system.set_density(&rho_half);
+                system.set_state(&x1, &v_half);
+                let a1 = system.acceleration();
+                let v1 = axpy(&v_half, &a1, dt / 2.);
+                system.set_density(&axpy(&rho_half, &system.density_rate(), dt / 2.));
+                system.set_state(&x1, &v1);
+                Some(a1)
+            }
+            Integrator::PredictorCorrector => {
+                let v_predict = axpy(&v0, acceleration, dt / 2.);
+                let x_predict = axpy(&x0, &system.drift_velocity(&v0), dt / 2.);
+                system.set_density(&axpy(&rho0, &r0, dt / 2.));
+                system.set_state(&x_predict, &v_predict);
+                let a_half = system.acceleration();
+
+                let v_half = axpy(&v0, &a_half, dt / 2.);
+                let x_half = axpy(&x0, &system.drift_velocity(&v_half), dt / 2.);
+                let rho_half = axpy(&rho0, &system.density_rate(), dt / 2.);
+                // x_{n+1} = 2 x_{n+1/2} - x_n
+                let x1 = axpy(&x_half, &axpy(&x_half, &x0, -1.), 1.);
+                let v1 = axpy(&v_half, &axpy(&v_half, &v0, -1.), 1.);
+                system.set_density(&axpy(&rho_half, &axpy(&rho_half, &rho0, -1.), 1.));
+                system.set_state(&x1, &v1);
+                None
+            }
+            Integrator::RungeKutta4 => {
+                let k1 = (system.drift_velocity(&v0), acceleration.to_vec(), r0);
+                let mut stage = |k: &(Vec<Vector>, Vec<Vector>, Vec<Real>), h: Real| {
+                    system.set_density(&axpy(&rho0, &k.2, h));
+                    system.set_state(&axpy(&x0, &k.0, h), &axpy(&v0, &k.1, h));
+                    let a = system.acceleration();
+                    (
+                        system.drift_velocity(&system.velocity()),
+                        a,
+                        system.density_rate(),
+                    )
+                };
+                let k2 = stage(&k1, dt / 2.);
+                let k3 = stage(&k2, dt / 2.);
+                let k4 = stage(&k3, dt);
+
+                let x1 = rk4_sum(&x0, [&k1.0, &k2.0, &k3.0, &k4.0], dt);
+                let v1 = rk4_sum(&v0, [&k1.1, &k2.1, &k3.1, &k4.1], dt);
+                system.set_density(&rk4_sum(&rho0, [&k1.2, &k2.2, &k3.2, &k4.2], dt));
+                system.set_state(&x1, &v1);
+                None
+            }
+        }
+    }
+}
+
+// x + y * a
+fn axpy<V>(x: &[V], y: &[V], a: Real) -> Vec<V>
+where
+    V: Copy + Add<Output = V> + Mul<Real, Output = V>,
+{
+    izip!(x, y).map(|(x, y)| *x + *y * a).collect()
+}
+
+// x + (k1 + 2 k2 + 2 k3 + k4) dt / 6
+fn rk4_sum<V>(x: &[V], k: [&[V]; 4], dt: Real) -> Vec<V>
+where
+    V: Copy + Add<Output = V> + Mul<Real, Output = V>,
+{
+    let x = axpy(x, k[0], dt / 6.);
+    let x = axpy(&x, k[1], dt / 3.);
+    let x = axpy(&x, k[2], dt / 3.);
+    axpy(&x, k[3], dt / 6.)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // x'' = -x, x(0) = 1, v(0) = 0, with density' = x, density(0) = 0
+    struct Oscillator {
+        position: Vec<Vector>,
+        velocity: Vec<Vector>,
+        density: Vec<Real>,
+        density_rate: Vec<Real>,
+    }
+
+    impl System for Oscillator {
+        fn position(&self) -> Vec<Vector> {
+            self.position.clone()
+        }
+
+        fn velocity(&self) -> Vec<Vector> {
+            self.velocity.clone()
+        }
+
+        fn set_state(&mut self, position: &[Vector], velocity: &[Vector]) {
+            self.position = position.to_vec();
+            self.velocity = velocity.to_vec();
+        }
+
+        fn acceleration(&mut self) -> Vec<Vector> {
+            self.density_rate = self.position.iter().map(|x| x.x).collect();
+            self.position.iter().map(|x| -*x).collect()
+        }
+
+        fn density(&self) -> Vec<Real> {
+            self.density.clone()
+        }
+
+        fn set_density(&mut self, density: &[Real]) {
+            self.density = density.to_vec();
+        }
+
+        fn density_rate(&self) -> Vec<Real> {
+            self.density_rate.clone()
+        }
+    }
+
+    fn error(integrator: Integrator, dt: Real, duration: Real) -> Real {
+        let mut system = Oscillator {
+            position: vec![Vector::X],
+            velocity: vec![Vector::ZERO],
+            density: vec![0.],
+            density_rate: vec![],
+        };
+        let mut acceleration = system.acceleration();
+        let steps = (duration / dt).round() as usize;
+        for _ in 0..steps {
+            acceleration = integrator
+                .step(&mut system, &acceleration, dt)
+                .unwrap_or_else(|| system.acceleration());
+        }
+        let t = steps as Real * dt;
+        let x = (system.position[0] - Vector::X * t.cos()).length();
+        let v = (system.velocity[0] + Vector::X * t.sin()).length();
+        let rho = (system.density[0] - t.sin()).abs();
+        x + v + rho
+    }
+
+    #[test]
+    fn oscillator_convergence() {
+        for integrator in Integrator::ALL {
+            let coarse = error(integrator, 0.4, 4.);
+            let fine = error(integrator, 0.2, 4.);
+            let order = (coarse / fine).log2();
+            assert!(
+                (order - integrator.order() as Real).abs() < 0.4,
+                "{integrator:?} converges with order {order}, error {coarse} at dt 0.4 and {fine} at dt 0.2"
+            );
+            assert_eq!(Integrator::from_name(integrator.name()), Some(integrator));
+        }
+    }
+}
@@ -1,14 +1,16 @@
 use crate::kernel::definition::KernelImpl;
-use std::f32::consts::PI;
+use crate::kernel::SimdKernel;
+use crate::real::{consts::PI, Real, RealLanes};
+use wide::CmpLe;
 
 #[derive(Debug, Clone, Copy)]
 pub struct CubicSpline {
-    h: f32,
-    constant: f32,
+    h: Real,
+    constant: Real,
 }
 
 impl KernelImpl for CubicSpline {
-    fn new(h: f32) -> Self {
+    fn new(h: Real) -> Self {
         let volume = 4. * h.powi(3) * PI;
         Self {
             h,
@@ -16,11 +18,15 @@ impl KernelImpl for CubicSpline {
         }
     }
 
-    fn support_radius_impl(&self) -> f32 {
+    fn smoothing_length_impl(&self) -> Real {
+        self.h
+    }
+
+    fn support_radius_impl(&self) -> Real {
         2. * self.h
     }
 
-    fn function_impl(&self, r: f32) -> f32 {
+    fn function_impl(&self, r: Real) -> Real {
         debug_assert!(r >= 0.0, "value of r: {}", r);
         let value = match r {
             x if x <= self.h => {
@@ -32,7 +38,7 @@ impl KernelImpl for CubicSpline {
         value * self.constant
     }
 
-    fn gradient_impl(&self, r: f32) -> f32 {
+    fn gradient_impl(&self, r: Real) -> Real {
         debug_assert!(r >= 0.0, "value of r: {}", r);
         let value = if r <= self.h {
             3. * r * (-4. * self.h + 3. * r) * self.h.powi(-3)
@@ -44,7 +50,7 @@ impl KernelImpl for CubicSpline {
         value * self.constant
     }
 
-    fn laplacian_impl(&self, r: f32) -> f32 {
+    fn laplacian_impl(&self, r: Real) -> Real {
         debug_assert!(r >= 0.0, "value of r: {}", r);
         let value = match r {
             x if x <= self.h => 6. * (-2. * self.h + 3. * r) * self.h.powi(-3),
@@ -55,10 +61,41 @@ impl KernelImpl for CubicSpline {
     }
 }
 
+impl SimdKernel for CubicSpline {
+    fn function_lanes(&self, r: RealLanes) -> RealLanes {
+        let h = RealLanes::splat(self.h);
+        let outer = RealLanes::splat(2.) * h - r;
+        let outer = outer * outer * outer;
+        let inner = h - r;
+        let inner = RealLanes::splat(-4.) * inner * inner * inner + outer;
+        let value = r
+            .cmp_le(h)
+            .blend(inner, r.cmp_le(h + h).blend(outer, RealLanes::ZERO));
+        value * RealLanes::splat(self.h.powi(-3) * self.constant)
+    }
+
+    fn gradient_lanes(&self, r: RealLanes) -> RealLanes {
+        Self::gradient_lanes_with(RealLanes::splat(self.h), r)
+    }
+
+    fn gradient_lanes_with(h: RealLanes, r: RealLanes) -> RealLanes {
+        let inner =
+            RealLanes::splat(3.) * r * (RealLanes::splat(-4.) * h + RealLanes::splat(3.) * r);
+        let outer = RealLanes::splat(2.) * h - r;
+        let outer = RealLanes::splat(-3.) * outer * outer;
+        let value = r
+            .cmp_le(h)
+            .blend(inner, r.cmp_le(h + h).blend(outer, RealLanes::ZERO));
+        let h3 = h * h * h;
+        value / (RealLanes::splat(4. * PI) * h3 * h3)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::super::tests_helper;
     use super::*;
+    use crate::real::{Vector, LANES};
     use std::path::PathBuf;
 
     const FILE_PATH: &str = "equation/samples/cubic_spline.json";
@@ -91,4 +128,37 @@ mod tests {
         let kernel = TestKernel::new(data.get_h());
         tests_helper::check_lapcian(kernel, &values);
     }
+
+    #[test]
+    fn lanes_match_scalar() {
+        let kernel = TestKernel::new(1.5);
+        let r = [0., 0.3, 1.2, 1.5, 1.9, 2.7, 3.0, 4.2];
+        for r in r.chunks(LANES) {
+            let lanes = RealLanes::from(<[Real; LANES]>::try_from(r).unwrap());
+            let function = kernel.function_lanes(lanes).to_array();
+            let gradient = kernel.gradient_lanes(lanes).to_array();
+            for (i, &r) in r.iter().enumerate() {
+                let tolerance = 1e-6;
+                assert!((function[i] - kernel.function_impl(r)).abs() <= tolerance);
+                assert!((gradient[i] - kernel.gradient_impl(r)).abs() <= tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn h_derivative_match_finite_difference() {
+        let h = 1.5;
+        let step = 1e-2;
+        for r in [0., 0.4, 1.2, 1.9, 2.8] {
+            let r = Vector::X * r;
+            let function =
+                |h| crate::kernel::Kernel::function(&<TestKernel as KernelImpl>::new(h), r);
+            let expect = (function(h + step) - function(h - step)) / (2. * step);
+            let value = crate::kernel::Kernel::h_derivative(&<TestKernel as KernelImpl>::new(h), r);
+            assert!(
+                (value - expect).abs() <= 1e-4,
+                "left: {value}, right: {expect}"
+            );
+        }
+    }
 }
@@ -1,44 +1,65 @@
-use macroquad::math::Vec3;
+use crate::real::{Real, Vector};
 
 pub trait KernelImpl {
-    fn new(h: f32) -> Self;
-    fn support_radius_impl(&self) -> f32;
-    fn function_impl(&self, r: f32) -> f32;
-    fn gradient_impl(&self, r: f32) -> f32;
-    fn laplacian_impl(&self, r: f32) -> f32;
+    fn new(h: Real) -> Self;
+    fn smoothing_length_impl(&self) -> Real;
+    fn support_radius_impl(&self) -> Real;
+    fn function_impl(&self, r: Real) -> Real;
+    fn gradient_impl(&self, r: Real) -> Real;
+    fn laplacian_impl(&self, r: Real) -> Real;
 }
 
 pub trait Kernel {
-    fn new(h: f32) -> Self;
-    fn support_radius(&self) -> f32;
-    fn function(&self, r: Vec3) -> f32;
-    fn gradient(&self, r: Vec3) -> Vec3;
-    fn laplacian(&self, r: Vec3) -> Vec3;
+    fn new(h: Real) -> Self;
+    fn smoothing_length(&self) -> Real;
+    fn support_radius(&self) -> Real;
+    fn function(&self, r: Vector) -> Real;
+    /// `dW/dh` at fixed `r`, used by the grad-h terms.
+    fn h_derivative(&self, r: Vector) -> Real;
+    fn gradient(&self, r: Vector) -> Vector;
+    /// `dW/dr / r`, the gradient is `r` times this, zero where `r` is zero.
+    fn gradient_scale(&self, r: Real) -> Real;
+    fn laplacian(&self, r: Vector) -> Vector;
 }
 
 impl<T: KernelImpl> Kernel for T {
-    fn new(h: f32) -> Self {
+    fn new(h: Real) -> Self {
         T::new(h)
     }
 
-    fn support_radius(&self) -> f32 {
+    fn smoothing_length(&self) -> Real {
+        self.smoothing_length_impl()
+    }
+
+    fn support_radius(&self) -> Real {
         self.support_radius_impl()
     }
 
-    fn function(&self, r: Vec3) -> f32 {
+    fn function(&self, r: Vector) -> Real {
         self.function_impl(r.length())
     }
 
-    fn gradient(&self, r: Vec3) -> Vec3 {
-        if r.length() == 0.0 {
-            return Vec3::ZERO;
+    // W = h^-3 f(r / h) in 3D, so dW/dh = -(3 W + r dW/dr) / h
+    fn h_derivative(&self, r: Vector) -> Real {
+        let length = r.length();
+        -(3. * self.function_impl(length) + length * self.gradient_impl(length))
+            / self.smoothing_length_impl()
+    }
+
+    fn gradient(&self, r: Vector) -> Vector {
+        r * self.gradient_scale(r.length())
+    }
+
+    fn gradient_scale(&self, r: Real) -> Real {
+        if r == 0.0 {
+            return 0.;
         }
-        r * self.gradient_impl(r.length())
+        self.gradient_impl(r) / r
     }
 
-    fn laplacian(&self, r: Vec3) -> Vec3 {
+    fn laplacian(&self, r: Vector) -> Vector {
         if r.length() == 0.0 {
-            return Vec3::ZERO;
+            return Vector::ZERO;
         }
         r * self.laplacian_impl(r.length())
     }
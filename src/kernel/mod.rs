@@ -1,12 +1,14 @@
 mod cubic_spline;
 mod definition;
 // mod poly6;
+mod simd;
 // mod spiky;
 pub(in crate::kernel) mod tests_helper;
 // mod viscosity;
 
 pub use cubic_spline::CubicSpline;
 pub use definition::Kernel;
+pub use simd::{gather, Lanes, SimdKernel, VectorLanes};
 // pub use poly6::Poly6;
 // pub use spiky::Spiky;
 // pub use viscosity::Viscosity;
@@ -0,0 +1,153 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::real::{Real, RealLanes, Vector, LANES};
+use wide::{CmpGt, CmpLt};
+
+use super::Kernel;
+
+/// Arithmetic shared by `Real` and `RealLanes`. A pair term written against it is the same
+/// code for one neighbour and for `LANES` of them.
+pub trait Lanes:
+    Copy
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    fn splat(value: Real) -> Self;
+    /// `value` where `self` is below zero, zero elsewhere.
+    fn if_negative(self, value: Self) -> Self;
+}
+
+impl Lanes for Real {
+    #[inline]
+    fn splat(value: Real) -> Self {
+        value
+    }
+
+    #[inline]
+    fn if_negative(self, value: Self) -> Self {
+        match self < 0. {
+            true => value,
+            false => 0.,
+        }
+    }
+}
+
+impl Lanes for RealLanes {
+    #[inline]
+    fn splat(value: Real) -> Self {
+        RealLanes::splat(value)
+    }
+
+    #[inline]
+    fn if_negative(self, value: Self) -> Self {
+        self.cmp_lt(RealLanes::ZERO).blend(value, RealLanes::ZERO)
+    }
+}
+
+/// Lane-wise kernel evaluation, `r` is the distance of `LANES` neighbours at once.
+pub trait SimdKernel: Kernel {
+    fn function_lanes(&self, r: RealLanes) -> RealLanes;
+    fn gradient_lanes(&self, r: RealLanes) -> RealLanes;
+    /// Same as `gradient_lanes` with a smoothing length per lane.
+    fn gradient_lanes_with(h: RealLanes, r: RealLanes) -> RealLanes;
+
+    /// `dW/dr / r`, the gradient is `r` times this, zero where `r` is zero.
+    #[inline]
+    fn gradient_scale_lanes(&self, r: RealLanes) -> RealLanes {
+        r.cmp_gt(RealLanes::ZERO)
+            .blend(self.gradient_lanes(r) / r, RealLanes::ZERO)
+    }
+
+    #[inline]
+    fn gradient_scale_lanes_with(h: RealLanes, r: RealLanes) -> RealLanes {
+        r.cmp_gt(RealLanes::ZERO)
+            .blend(Self::gradient_lanes_with(h, r) / r, RealLanes::ZERO)
+    }
+}
+
+/// Load up to `LANES` values, unused lanes are filled with zero.
+#[inline]
+pub fn gather(index: &[usize], value: impl Fn(usize) -> Real) -> RealLanes {
+    debug_assert!(index.len() <= LANES);
+    let mut lanes = [0.; LANES];
+    lanes.iter_mut().zip(index).for_each(|(l, &i)| *l = value(i));
+    RealLanes::from(lanes)
+}
+
+/// `LANES` vectors stored component-wise.
+#[derive(Debug, Clone, Copy)]
+pub struct VectorLanes {
+    pub x: RealLanes,
+    pub y: RealLanes,
+    pub z: RealLanes,
+}
+
+impl VectorLanes {
+    #[inline]
+    pub fn splat(value: Vector) -> Self {
+        Self {
+            x: RealLanes::splat(value.x),
+            y: RealLanes::splat(value.y),
+            z: RealLanes::splat(value.z),
+        }
+    }
+
+    #[inline]
+    pub fn gather(index: &[usize], values: &[Vector]) -> Self {
+        Self {
+            x: gather(index, |i| values[i].x),
+            y: gather(index, |i| values[i].y),
+            z: gather(index, |i| values[i].z),
+        }
+    }
+
+    #[inline]
+    pub fn dot(self, rhs: Self) -> RealLanes {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    #[inline]
+    pub fn length_squared(self) -> RealLanes {
+        self.dot(self)
+    }
+
+    #[inline]
+    pub fn length(self) -> RealLanes {
+        self.length_squared().sqrt()
+    }
+
+    /// Horizontal sum over all lanes.
+    #[inline]
+    pub fn sum(self) -> Vector {
+        Vector::new(self.x.reduce_add(), self.y.reduce_add(), self.z.reduce_add())
+    }
+}
+
+impl Sub for VectorLanes {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            x: self.x - rhs.x,
+            y: self.y - rhs.y,
+            z: self.z - rhs.z,
+        }
+    }
+}
+
+impl Mul<RealLanes> for VectorLanes {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, rhs: RealLanes) -> Self {
+        Self {
+            x: self.x * rhs,
+            y: self.y * rhs,
+            z: self.z * rhs,
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use super::definition::KernelImpl;
+use crate::real::Real;
 use core::panic;
 use itertools::Itertools;
 use serde::Deserialize;
@@ -7,12 +8,12 @@ use std::{fmt::Debug, fs, path::PathBuf};
 
 #[derive(Deserialize, Clone)]
 pub(crate) struct Value {
-    r: f32,
-    w: f32,
+    r: Real,
+    w: Real,
 }
 
-impl From<(f32, f32)> for Value {
-    fn from(value: (f32, f32)) -> Self {
+impl From<(Real, Real)> for Value {
+    fn from(value: (Real, Real)) -> Self {
         Self {
             r: value.0,
             w: value.1,
@@ -23,8 +24,8 @@ impl From<(f32, f32)> for Value {
 impl From<Vec<f64>> for Value {
     fn from(value: Vec<f64>) -> Self {
         assert_eq!(value.len(), 2);
-        let r = value[0] as f32;
-        let w = value[1] as f32;
+        let r = value[0] as Real;
+        let w = value[1] as Real;
         assert!(r.is_finite());
         assert!(w.is_finite());
         Self { r, w }
@@ -58,8 +59,8 @@ impl TestData {
         }
     }
 
-    pub fn get_h(&self) -> f32 {
-        let ret = self.h_value as f32;
+    pub fn get_h(&self) -> Real {
+        let ret = self.h_value as Real;
         assert!(ret.is_finite());
         ret
     }
@@ -75,7 +76,7 @@ macro_rules! check_impl {
             values.iter().for_each(|Value { r, w }| {
                 let ret = kernel.$call_function(*r);
                 // 3 decimal places precision
-                let factor = 10f32.powi(3);
+                let factor = Real::powi(10., 3);
                 let ret_ = (ret * factor).trunc();
                 let w_ = (w * factor).trunc();
                 assert_eq!(
@@ -0,0 +1,147 @@
+use std::marker::PhantomData;
+
+use crate::kernel::Kernel;
+use crate::profiler;
+use crate::real::*;
+use crate::util_3d::*;
+
+/// Solid made of particles that never move. Fluid particles closer than `spacing` are
+/// pushed back with the Lennard-Jones like force of Monaghan 1994,
+/// `D ((r_0 / r)^12 - (r_0 / r)^4) r / r^2`.
+/// A static contact angle can be given for the fluid on this solid, for the surface tension
+/// models that can hold it, and a temperature it is held at for heat conduction.
+#[derive(Debug)]
+pub struct Boundary<T: Kernel> {
+    solid: Space,
+    /// Volume each solid particle stands for, `1 / sum_k W_ak` over the solid (Akinci et al. 2012).
+    volume: Vec<Real>,
+    spacing: Real,
+    strength: Real,
+    contact_angle: Option<Real>,
+    temperature: Option<Real>,
+    _kernel: PhantomData<T>,
+}
+
+impl<T: Kernel> Boundary<T> {
+    /// `strength` is `D` in cm^2/s^2, about the square of the largest speed.
+    pub fn new(particles: Vec<Particle>, spacing: Real, strength: Real) -> Self {
+        assert!(spacing > 0. && strength >= 0.);
+        let grid_size = particles
+            .iter()
+            .map(|p| p.kernel_radius)
+            .fold(0., Real::max);
+        let solid = Space::new(grid_size, particles);
+        let volume = solid.collect_by_id(|a| {
+            let kernel = T::new(a.kernel_radius);
+            let sum = solid
+                .neighbour(a, kernel.support_radius())
+                .map(|b| kernel.function(a.position - b.position))
+                .sum::<Real>();
+            1. / sum
+        });
+        Self {
+            solid,
+            volume,
+            spacing,
+            strength,
+            contact_angle: None,
+            temperature: None,
+            _kernel: PhantomData,
+        }
+    }
+
+    /// `angle` in radians, measured through the fluid, below a right angle the fluid wets
+    /// the solid.
+    pub fn with_contact_angle(self, angle: Real) -> Self {
+        assert!((0. ..=consts::PI).contains(&angle));
+        Self {
+            contact_angle: Some(angle),
+            ..self
+        }
+    }
+
+    pub fn contact_angle(&self) -> Option<Real> {
+        self.contact_angle
+    }
+
+    /// In degrees Celsius, whatever heat the fluid brings or takes. Without one the solid
+    /// does not conduct.
+    pub fn with_temperature(self, temperature: Real) -> Self {
+        Self {
+            temperature: Some(temperature),
+            ..self
+        }
+    }
+
+    pub fn temperature(&self) -> Option<Real> {
+        self.temperature
+    }
+
+    /// Unit normal of the solid near `particle`, pointing out of it, zero when the solid is
+    /// farther than the kernel support.
+    pub fn normal(&self, particle: &Particle) -> Vector {
+        let kernel = T::new(particle.kernel_radius);
+        let sum = self
+            .solid
+            .neighbour(particle, kernel.support_radius())
+            .map(|k| kernel.gradient(particle.position - k.position) * self.volume[k.id])
+            .fold(Vector::ZERO, |s, g| s + g);
+        -sum.normalize_or_zero()
+    }
+
+    pub fn solid(&self) -> &Space {
+        &self.solid
+    }
+
+    /// By solid particle id.
+    pub fn volume(&self) -> &[Real] {
+        &self.volume
+    }
+
+    /// Repulsion on every `fluid.active_particles()`.
+    pub fn accelration(&self, fluid: &Space) -> Vec<Vector> {
+        let _scope = profiler::scope("Boundary::accelration");
+        fluid
+            .active_particles()
+            .map(|a| {
+                self.solid
+                    .neighbour(a, self.spacing)
+                    .map(|k| {
+                        let r = a.position - k.position;
+                        let distance = r.length();
+                        if distance >= self.spacing || distance == 0. {
+                            return Vector::ZERO;
+                        }
+                        let ratio = self.spacing / distance;
+                        r * (self.strength * (ratio.powi(12) - ratio.powi(4)) / r.length_squared())
+                    })
+                    .fold(Vector::ZERO, |s, f| s + f)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::CubicSpline;
+
+    #[test]
+    fn push_out_of_solid() {
+        let h = 1.3;
+        let plate = init_setup::create_plate(1., 9, Vector::ZERO, 1., h);
+        let boundary = Boundary::<CubicSpline>::new(plate, 1., 100.);
+        // a full plane of spacing 1 stands for about one unit of area times one spacing
+        let volume = boundary.volume()[40];
+        assert!((0.5..2.).contains(&volume), "{volume}");
+
+        let fluid = [0.5, 0.99, 1.5]
+            .map(|z| Particle::new(vector(0., 0., z), Vector::ZERO, 1., h))
+            .to_vec();
+        let fluid = Space::new(h, fluid);
+        let acceleration = fluid.order_by_id(boundary.accelration(&fluid));
+        assert!(acceleration[0].z > 0. && acceleration[1].z > 0.);
+        assert!(acceleration[0].z > acceleration[1].z);
+        assert_eq!(acceleration[2], Vector::ZERO);
+    }
+}
@@ -0,0 +1,167 @@
+use std::marker::PhantomData;
+
+use crate::kernel::Kernel;
+use crate::profiler;
+use crate::real::*;
+use crate::util_3d::*;
+
+use super::SmoothingLength;
+
+/// Summation density with a kernel radius consistent with it, `h = eta (m / rho)^(1/3)`
+/// (Springel & Hernquist 2002, Price 2012).
+#[derive(Debug)]
+pub struct GradH<T: Kernel> {
+    smoothing_length: SmoothingLength,
+    /// Relative change of `h` at which Newton-Raphson stops.
+    tolerance: Real,
+    max_iterations: usize,
+    _kernel: PhantomData<T>,
+}
+
+impl<T: Kernel> GradH<T> {
+    pub fn new(smoothing_length: SmoothingLength, tolerance: Real, max_iterations: usize) -> Self {
+        Self {
+            smoothing_length,
+            tolerance,
+            max_iterations,
+            _kernel: PhantomData,
+        }
+    }
+
+    /// Solve `h` and `rho` of every active particle together, then set its `omega`.
+    /// Return the largest number of iterations any particle needed.
+    pub fn update_density(&self, space: &mut Space) -> usize {
+        let _scope = profiler::scope("GradH::update_density");
+        let solution = space
+            .active_particles()
+            .map(|a| self.solve(space, a))
+            .collect::<Vec<_>>();
+
+        let iterations = solution.iter().map(|s| s.3).max().unwrap_or(0);
+        space
+            .active_particles_mut()
+            .zip(solution)
+            .for_each(|(p, (h, density, omega, _))| {
+                p.kernel_radius = h;
+                p.density = density;
+                p.omega = omega;
+            });
+        iterations
+    }
+
+    // Newton-Raphson on f(h) = sum m_b W(h) + offset - m (eta / h)^3
+    fn solve(&self, space: &Space, a: &Particle) -> (Real, Real, Real, usize) {
+        let Some(eta) = self.smoothing_length.eta::<T>() else {
+            let h = self.smoothing_length.kernel_radius::<T>(a.mass, a.density);
+            return (h, summation::<T>(space, a, h).0, 1., 1);
+        };
+
+        let offset = self.smoothing_length.density_offset();
+        let mut h = self.smoothing_length.clamp(a.kernel_radius);
+        let mut iterations = 0;
+        loop {
+            let (density, dh) = summation::<T>(space, a, h);
+            let target = a.mass * (eta / h).powi(3);
+            let slope = dh + 3. * target / h;
+            // omega = 1 - dh/drho sum m_b dW/dh, with dh/drho = -h / (3 (rho + offset))
+            let omega = 1. + h / (3. * (density + offset)) * dh;
+
+            let next = (h - (density + offset - target) / slope).clamp(h / 2., 2. * h);
+            let next = self.smoothing_length.clamp(next);
+            iterations += 1;
+            if (next - h).abs() <= self.tolerance * h || iterations >= self.max_iterations {
+                return (h, density, omega, iterations);
+            }
+            h = next;
+        }
+    }
+}
+
+// sum m_b W_ab and sum m_b dW_ab/dh at smoothing length h
+fn summation<T: Kernel>(space: &Space, a: &Particle, h: Real) -> (Real, Real) {
+    let kernel = T::new(h);
+    space
+        .neighbour(a, kernel.support_radius())
+        .map(|b| {
+            let r = a.position - b.position;
+            (b.mass * kernel.function(r), b.mass * kernel.h_derivative(r))
+        })
+        .fold((0., 0.), |s, v| (s.0 + v.0, s.1 + v.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::CubicSpline;
+    use crate::model::density::smoothing_length::SmoothingRule;
+    use crate::model::{pressure::Tait, viscosity::Artificial};
+    use macroquad::rand::{gen_range, srand};
+
+    // jittered cube, denser towards -x so every particle ends up with its own h
+    fn cloud() -> Vec<Particle> {
+        srand(11);
+        let mut particles = init_setup::create_cube(1., 8, Vector::ZERO, 1., 1.3);
+        particles.iter_mut().for_each(|p| {
+            let jitter = vector(
+                gen_range(-0.2, 0.2),
+                gen_range(-0.2, 0.2),
+                gen_range(-0.2, 0.2),
+            );
+            p.position += jitter;
+            p.position.x *= 1. + 0.05 * p.position.x;
+        });
+        particles
+    }
+
+    #[test]
+    fn consistent_h() {
+        let (eta, offset) = (1.3, 0.1);
+        let smoothing_length = SmoothingLength::new(SmoothingRule::Adaptive { eta, offset });
+        let model = GradH::<CubicSpline>::new(smoothing_length, 1e-5, 50);
+        let mut space = Space::new(1.3, cloud());
+
+        let iterations = model.update_density(&mut space);
+        assert!(iterations < 50);
+        for p in space.particles() {
+            let h = eta * (p.mass / (p.density + offset)).powf(1. / 3.);
+            assert!(
+                (p.kernel_radius - h).abs() <= 1e-3 * h,
+                "{} != {h}",
+                p.kernel_radius
+            );
+            assert!(p.omega > 0. && p.omega.is_finite());
+        }
+    }
+
+    // pair forces cancel even when every particle has its own kernel radius
+    #[test]
+    fn momentum_conservation() {
+        let model = GradH::<CubicSpline>::new(SmoothingLength::default(), 1e-5, 50);
+        let pressure_model = Tait::<CubicSpline>::new(0.5, 7, 20.);
+        let viscosity_model = Artificial::<CubicSpline>::new(0.5, 20.);
+
+        let mut particles = cloud();
+        particles
+            .iter_mut()
+            .for_each(|p| p.velocity = vector(p.position.y, -p.position.z, p.position.x));
+        let mut space = Space::new(1.3, particles);
+        model.update_density(&mut space);
+        pressure_model.update_pressure(&mut space);
+
+        let mass = space.collect_by_id(|p| p.mass);
+        for acceleration in [
+            pressure_model.accelration(&space),
+            viscosity_model.accelration(&space),
+        ] {
+            let acceleration = space.order_by_id(acceleration);
+            let (momentum, scale) = acceleration
+                .iter()
+                .zip(&mass)
+                .fold((Vector::ZERO, 0.), |(p, s), (a, m)| {
+                    (p + *a * *m, s + a.length() * m)
+                });
+            assert!(scale > 0.);
+            assert!(momentum.length() <= 1e-5 * scale, "{momentum:?} of {scale}");
+        }
+    }
+}
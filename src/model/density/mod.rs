@@ -0,0 +1,64 @@
+mod continuity;
+mod delta_sph;
+mod grad_h;
+mod smoothing_length;
+mod summation;
+
+pub use continuity::{Continuity, Reinit};
+pub use delta_sph::DeltaSph;
+pub use grad_h::GradH;
+pub use smoothing_length::SmoothingLength;
+pub(crate) use summation::Density;
+
+use crate::real::Real;
+
+/// How particle density is found each step.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DensityMethod {
+    /// Kernel summation over the neighbours, underestimates density at a free surface.
+    #[default]
+    Summation,
+    /// Summation of the number of neighbours times the particle's own mass
+    /// (Solenthaler and Pajarola 2008), keeps fluids of different density apart sharply.
+    /// Pressure forces then use the number density too.
+    NumberDensity,
+    /// Summation with every kernel radius solved together with its density,
+    /// forces then use the grad-h correction terms.
+    GradH,
+    /// Integrate `d rho / dt = sum m_b (v_a - v_b) . grad W_ab` in time,
+    /// re-initialised with `reinit` every `interval` steps.
+    Continuity { reinit: Reinit, interval: usize },
+}
+
+/// delta-SPH settings, only used with `DensityMethod::Continuity`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Diffusion {
+    pub delta: Real,
+    /// Antuono et al. 2010 when set, Molteni & Colagrossi 2009 otherwise.
+    pub gradient_correction: bool,
+}
+
+impl Default for Diffusion {
+    fn default() -> Self {
+        Self {
+            delta: 0.1,
+            gradient_correction: true,
+        }
+    }
+}
+
+impl DensityMethod {
+    /// `summation`, `number_density`, `grad_h`, `continuity`, or `shepard` / `mls` for continuity re-initialised every `interval` steps.
+    pub fn from_name(name: &str, interval: usize) -> Option<Self> {
+        let continuity = |reinit| DensityMethod::Continuity { reinit, interval };
+        match name {
+            "summation" => Some(DensityMethod::Summation),
+            "number_density" => Some(DensityMethod::NumberDensity),
+            "grad_h" => Some(DensityMethod::GradH),
+            "continuity" => Some(continuity(Reinit::None)),
+            "shepard" => Some(continuity(Reinit::Shepard)),
+            "mls" => Some(continuity(Reinit::Mls)),
+            _ => None,
+        }
+    }
+}
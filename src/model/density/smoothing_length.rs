@@ -0,0 +1,207 @@
+use std::fmt;
+
+use crate::kernel::Kernel;
+use crate::real::{consts::PI, Real};
+
+/// How the smoothing length of a particle follows its density.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmoothingRule {
+    /// Every particle keeps this `h`.
+    Fixed(Real),
+    /// `h = eta (m / (rho + offset))^(1/3)`, the offset keeps `h` finite for an isolated
+    /// particle.
+    Adaptive { eta: Real, offset: Real },
+    /// Adaptive with `eta` chosen so the kernel support holds about this many neighbours.
+    NeighbourCount(Real),
+}
+
+/// Smoothing length policy, `rule` limited to `[min, max]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmoothingLength {
+    pub rule: SmoothingRule,
+    pub min: Real,
+    pub max: Real,
+}
+
+const DEFAULT_OFFSET: Real = 0.1;
+
+impl Default for SmoothingLength {
+    fn default() -> Self {
+        Self::new(SmoothingRule::Adaptive {
+            eta: 1.3,
+            offset: DEFAULT_OFFSET,
+        })
+    }
+}
+
+impl SmoothingLength {
+    pub fn new(rule: SmoothingRule) -> Self {
+        Self {
+            rule,
+            min: 0.,
+            max: Real::INFINITY,
+        }
+    }
+
+    pub fn clamped(self, min: Real, max: Real) -> Self {
+        assert!(min <= max, "empty smoothing length range [{min}, {max}]");
+        Self { min, max, ..self }
+    }
+
+    /// `fixed=<h>`, `adaptive=<eta>[,<offset>]` or `neighbours=<count>`, the offset defaults
+    /// to the one of the default rule.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (kind, value) = name.split_once('=')?;
+        let rule = match kind {
+            "fixed" => SmoothingRule::Fixed(value.parse().ok()?),
+            "adaptive" => {
+                let (eta, offset) = value.split_once(',').unwrap_or((value, ""));
+                SmoothingRule::Adaptive {
+                    eta: eta.parse().ok()?,
+                    offset: match offset {
+                        "" => DEFAULT_OFFSET,
+                        offset => offset.parse().ok()?,
+                    },
+                }
+            }
+            "neighbours" => SmoothingRule::NeighbourCount(value.parse().ok()?),
+            _ => return None,
+        };
+        Some(Self::new(rule))
+    }
+
+    /// `h / (m / rho)^(1/3)`, `None` when `h` does not depend on density.
+    pub fn eta<T: Kernel>(&self) -> Option<Real> {
+        match self.rule {
+            SmoothingRule::Fixed(_) => None,
+            SmoothingRule::Adaptive { eta, .. } => Some(eta),
+            SmoothingRule::NeighbourCount(count) => Some(neighbour_eta::<T>(count)),
+        }
+    }
+
+    /// Added to the density before `eta` applies, zero unless the rule is `Adaptive`.
+    pub fn density_offset(&self) -> Real {
+        match self.rule {
+            SmoothingRule::Adaptive { offset, .. } => offset,
+            _ => 0.,
+        }
+    }
+
+    pub fn kernel_radius<T: Kernel>(&self, mass: Real, density: Real) -> Real {
+        let h = match self.rule {
+            SmoothingRule::Fixed(h) => h,
+            SmoothingRule::Adaptive { eta, offset } => {
+                eta * (mass / (density + offset)).powf(1. / 3.)
+            }
+            SmoothingRule::NeighbourCount(count) => {
+                neighbour_eta::<T>(count) * (mass / density).powf(1. / 3.)
+            }
+        };
+        self.clamp(h)
+    }
+
+    /// Radius of a particle sitting at `density`, without the offset that only keeps the
+    /// density-driven update finite.
+    pub fn rest_kernel_radius<T: Kernel>(&self, mass: Real, density: Real) -> Real {
+        let rule = match self.rule {
+            SmoothingRule::Adaptive { eta, .. } => SmoothingRule::Adaptive { eta, offset: 0. },
+            rule => rule,
+        };
+        Self { rule, ..*self }.kernel_radius::<T>(mass, density)
+    }
+
+    pub fn clamp(&self, h: Real) -> Real {
+        h.clamp(self.min, self.max)
+    }
+}
+
+// 4/3 pi (support h)^3 rho = count m
+fn neighbour_eta<T: Kernel>(count: Real) -> Real {
+    let support = T::new(1.).support_radius();
+    (3. * count / (4. * PI)).powf(1. / 3.) / support
+}
+
+impl fmt::Display for SmoothingLength {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rule {
+            SmoothingRule::Fixed(h) => write!(f, "fixed {h}")?,
+            SmoothingRule::Adaptive { eta, offset } => {
+                write!(f, "adaptive eta {eta}")?;
+                if offset > 0. {
+                    write!(f, " offset {offset}")?;
+                }
+            }
+            SmoothingRule::NeighbourCount(count) => write!(f, "{count} neighbours")?,
+        }
+        if self.min > 0. || self.max < Real::INFINITY {
+            write!(f, " in [{}, {}]", self.min, self.max)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::CubicSpline;
+    use crate::real::*;
+    use crate::util_3d::*;
+
+    #[test]
+    fn neighbour_count_target() {
+        let target = 60.;
+        let policy = SmoothingLength::new(SmoothingRule::NeighbourCount(target));
+        let h = policy.kernel_radius::<CubicSpline>(1., 1.);
+
+        let space = Space::new(h, init_setup::create_cube(1., 15, Vector::ZERO, 1., h));
+        let centre = space
+            .particles()
+            .min_by(|a, b| a.position.length().total_cmp(&b.position.length()))
+            .unwrap();
+        let radius = CubicSpline::new(h).support_radius();
+        let count = space
+            .neighbour(centre, radius)
+            .filter(|p| (p.position - centre.position).length() <= radius)
+            .count() as Real;
+        assert!(
+            (count - target).abs() <= 0.15 * target,
+            "{count} != {target}"
+        );
+    }
+
+    #[test]
+    fn rules_and_range() {
+        let fixed = SmoothingLength::from_name("fixed=1.5").unwrap();
+        assert_eq!(fixed.kernel_radius::<CubicSpline>(1., 0.1), 1.5);
+        assert_eq!(fixed.kernel_radius::<CubicSpline>(1., 10.), 1.5);
+
+        let adaptive = SmoothingLength::from_name("adaptive=1.3,0").unwrap();
+        let h = adaptive.kernel_radius::<CubicSpline>(8., 1.);
+        assert!((h - 2.6).abs() < 1e-5);
+        assert_eq!(
+            SmoothingLength::from_name("adaptive=1.3"),
+            Some(SmoothingLength::default())
+        );
+
+        let clamped = adaptive.clamped(1., 2.);
+        assert_eq!(clamped.kernel_radius::<CubicSpline>(8., 1.), 2.);
+        assert_eq!(clamped.kernel_radius::<CubicSpline>(1., 1e3), 1.);
+        assert_eq!(clamped.to_string(), "adaptive eta 1.3 in [1, 2]");
+        assert_eq!(SmoothingLength::from_name("eta=1.3"), None);
+    }
+
+    #[test]
+    fn default_rule() {
+        // h = 1.3 (m / (0.1 + rho))^(1/3), as the density model always did
+        let policy = SmoothingLength::default();
+        for (mass, density) in [(1., 1.), (8., 0.9), (0.5, 0.), (2., 1e-3)] {
+            let h = policy.kernel_radius::<CubicSpline>(mass, density);
+            let expected = 1.3 * (mass / (0.1 + density)).powf(1. / 3.);
+            assert!((h - expected).abs() <= 1e-5 * expected);
+        }
+        assert_eq!(policy.to_string(), "adaptive eta 1.3 offset 0.1");
+        // the setup keeps the baseline 1.3 (m / rho)^(1/3)
+        let h = policy.rest_kernel_radius::<CubicSpline>(8., 1.);
+        assert!((h - 2.6).abs() < 1e-5);
+    }
+}
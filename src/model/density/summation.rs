@@ -0,0 +1,171 @@
+use std::marker::PhantomData;
+
+use crate::kernel::{self, gather, Lanes, SimdKernel, VectorLanes};
+use crate::profiler;
+use crate::real::*;
+use crate::util_3d::*;
+use rayon::prelude::*;
+
+use super::SmoothingLength;
+
+#[derive(Debug)]
+pub(crate) struct Density<T: kernel::Kernel> {
+    smoothing_length: SmoothingLength,
+    number_density: bool,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: kernel::Kernel + Sync + Send> Density<T> {
+    pub fn new(smoothing_length: SmoothingLength) -> Self {
+        Self {
+            smoothing_length,
+            number_density: false,
+            _phantom: PhantomData::default(),
+        }
+    }
+
+    /// `rho_a = m_a sum W_ab` (Solenthaler and Pajarola 2008) instead of `sum m_b W_ab`,
+    /// so next to a fluid of another density a particle keeps its own.
+    pub fn with_number_density(self) -> Self {
+        Self {
+            number_density: true,
+            ..self
+        }
+    }
+
+    // `b` adds its kernel, weighted with the mass of `a` for a number density,
+    // shared by every loop below so that they sum the same thing
+    fn pair<S: Lanes>(&self, mass_a: S, mass_b: S, function: S) -> S {
+        let mass = match self.number_density {
+            true => mass_a,
+            false => mass_b,
+        };
+        mass * function
+    }
+
+    /// Only the active particles are updated, the others keep their density and kernel radius.
+    pub fn update_density(&self, space: &mut Space) {
+        let _scope = profiler::scope("Density::update_density");
+        let density = space
+            .par_active_particles()
+            .map(|a| {
+                let kernel = T::new(a.kernel_radius);
+                let others = space.neighbour(a, kernel.support_radius());
+                others
+                    .map(|b| self.pair(a.mass, b.mass, kernel.function(a.position - b.position)))
+                    .sum::<Real>()
+            })
+            .collect::<Vec<_>>();
+        let density = space.order_by_id(density);
+        self.set_density(space, &density);
+    }
+
+    /// Density of the active particles from `density`, indexed by id, along with the kernel
+    /// radius that goes with it.
+    pub fn set_density(&self, space: &mut Space, density: &[Real]) {
+        space.active_particles_mut().for_each(|particle| {
+            particle.density = density[particle.id];
+            particle.kernel_radius = self
+                .smoothing_length
+                .kernel_radius::<T>(particle.mass, particle.density);
+        });
+    }
+
+    /// Kernel summation over a snapshot with precomputed neighbour lists, indexed by id.
+    pub fn density_soa(&self, soa: &ParticleSoa, neighbours: &NeighbourList) -> Vec<Real> {
+        let _scope = profiler::scope("Density::density_soa");
+        (0..soa.len())
+            .into_par_iter()
+            .map(|a| {
+                let kernel = T::new(soa.kernel_radius[a]);
+                neighbours
+                    .of(a)
+                    .iter()
+                    .map(|&b| {
+                        let function = kernel.function(soa.position[a] - soa.position[b]);
+                        self.pair(soa.mass[a], soa.mass[b], function)
+                    })
+                    .sum::<Real>()
+            })
+            .collect::<Vec<_>>()
+    }
+
+    /// Same as `density_soa`, `LANES` neighbours at a time.
+    pub fn density_simd(&self, soa: &ParticleSoa, neighbours: &NeighbourList) -> Vec<Real>
+    where
+        T: SimdKernel,
+    {
+        let _scope = profiler::scope("Density::density_simd");
+        (0..soa.len())
+            .into_par_iter()
+            .map(|a| {
+                let kernel = T::new(soa.kernel_radius[a]);
+                let position_a = VectorLanes::splat(soa.position[a]);
+                neighbours
+                    .of(a)
+                    .chunks(LANES)
+                    .map(|index| {
+                        let r = position_a - VectorLanes::gather(index, &soa.position);
+                        // unused lanes have zero mass on both sides
+                        let mass_a = gather(index, |_| soa.mass[a]);
+                        let mass_b = gather(index, |b| soa.mass[b]);
+                        let function = kernel.function_lanes(r.length());
+                        self.pair(mass_a, mass_b, function).reduce_add()
+                    })
+                    .sum::<Real>()
+            })
+            .collect::<Vec<_>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::CubicSpline;
+
+    #[test]
+    fn simd_match_scalar() {
+        let h = 1.3;
+        let density_model = Density::<CubicSpline>::new(SmoothingLength::default());
+        let mut space = Space::new(h, init_setup::create_cube(1., 5, Vector::ZERO, 1., h));
+        density_model.update_density(&mut space);
+
+        let soa = space.to_soa();
+        let neighbours = NeighbourList::new::<CubicSpline>(&soa);
+        let scalar = density_model.density_soa(&soa, &neighbours);
+        let simd = density_model.density_simd(&soa, &neighbours);
+
+        for (a, b) in scalar.into_iter().zip(simd) {
+            assert!((a - b).abs() <= 1e-5, "left: {:?}, right: {:?}", a, b);
+        }
+    }
+
+    // a heavy fluid under a light one, both at the same spacing
+    #[test]
+    fn number_density_at_interface() {
+        let h = 1.3;
+        let mut particles = init_setup::create_cube(1., 9, Vector::ZERO, 1., h);
+        particles
+            .iter_mut()
+            .filter(|p| p.position.z < 0.)
+            .for_each(|p| p.mass = 2.);
+        let density = |model: Density<CubicSpline>| {
+            let mut space = Space::new(h, particles.clone());
+            model.update_density(&mut space);
+            let mut density = space
+                .particles()
+                .filter(|p| p.position.truncate().length() < 0.5)
+                .filter(|p| p.position.z == -1. || p.position.z == 0.)
+                .map(|p| (p.position.z, p.density / p.mass))
+                .collect::<Vec<_>>();
+            density.sort_by(|a, b| a.0.total_cmp(&b.0));
+            [density[0].1, density[1].1]
+        };
+
+        let [heavy, light] = density(Density::new(SmoothingLength::default()));
+        assert!(heavy < 0.9 * light, "{heavy} {light}");
+        let [heavy, light] =
+            density(Density::new(SmoothingLength::default()).with_number_density());
+        assert!((heavy - light).abs() <= 1e-4 * light, "{heavy} {light}");
+    }
+}
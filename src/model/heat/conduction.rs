@@ -0,0 +1,236 @@
+use std::marker::PhantomData;
+
+use itertools::izip;
+
+use crate::kernel::Kernel;
+use crate::model::boundary::Boundary;
+use crate::profiler;
+use crate::real::*;
+use crate::util_3d::*;
+
+/// Heat conduction of Cleary and Monaghan 1999,
+/// `c_a dT_a / dt = sum m_b / (rho_a rho_b) 4 k_a k_b / (k_a + k_b) (T_a - T_b) r . grad W / (r^2 + 0.01 h^2)`.
+/// The harmonic mean of the conductivities keeps the flux continuous where two materials
+/// meet, and whatever heat one particle of a pair loses the other gains.
+///
+/// A solid held at a temperature joins the sum with the volume of its particles, conducting
+/// as well as the fluid next to it.
+#[derive(Debug)]
+pub struct Conduction<T: Kernel> {
+    /// In erg/(s cm K), indexed by `Particle::material`.
+    conductivity: Vec<Real>,
+    /// In erg/(g K), indexed by `Particle::material`.
+    heat_capacity: Vec<Real>,
+    _kernel: PhantomData<T>,
+}
+
+impl<T: Kernel + Sync + Send> Conduction<T> {
+    /// One conductivity and one heat capacity per material.
+    pub fn new(conductivity: Vec<Real>, heat_capacity: Vec<Real>) -> Self {
+        assert_eq!(conductivity.len(), heat_capacity.len());
+        assert!(conductivity.iter().all(|&k| k >= 0.));
+        assert!(heat_capacity.iter().all(|&c| c > 0.));
+        Self {
+            conductivity,
+            heat_capacity,
+            _kernel: PhantomData,
+        }
+    }
+
+    /// Largest thermal diffusivity `k / (rho c)` over the materials, in cm^2/s.
+    pub fn diffusivity(&self, density: Real) -> Real {
+        let diffusivity = izip!(&self.conductivity, &self.heat_capacity).map(|(k, c)| k / c);
+        diffusivity.fold(0., Real::max) / density
+    }
+
+    /// `dT / dt` in K/s, one entry per `space.active_particles()`, with `boundary` the solid
+    /// the fluid touches.
+    pub fn temperature_rate(&self, space: &Space, boundary: Option<&Boundary<T>>) -> Vec<Real> {
+        let _scope = profiler::scope("Conduction::temperature_rate");
+        let radius = T::new(space.max_kernel_radius()).support_radius();
+        let wall = boundary.and_then(|b| b.temperature().map(|t| (b, t)));
+        // `r . grad W / (r^2 + 0.01 h^2)`, negative
+        let falloff = |r: Vector, gradient: Vector, h: Real| {
+            r.dot(gradient) / (r.length_squared() + 0.01 * h * h)
+        };
+        space
+            .active_particles()
+            .map(|a| {
+                let kernel = T::new(a.kernel_radius);
+                let conductivity = self.conductivity[a.material];
+                let fluid = space
+                    .neighbour(a, radius)
+                    .filter(|b| b.id != a.id)
+                    .map(|b| {
+                        let r = a.position - b.position;
+                        let h = (a.kernel_radius + b.kernel_radius) / 2.;
+                        let gradient =
+                            (kernel.gradient(r) + T::new(b.kernel_radius).gradient(r)) / 2.;
+                        let k = match conductivity + self.conductivity[b.material] {
+                            sum if sum > 0. => {
+                                4. * conductivity * self.conductivity[b.material] / sum
+                            }
+                            _ => 0.,
+                        };
+                        b.mass / b.density
+                            * k
+                            * (a.temperature - b.temperature)
+                            * falloff(r, gradient, h)
+                    })
+                    .sum::<Real>();
+                let solid = wall.map_or(0., |(boundary, temperature)| {
+                    boundary
+                        .solid()
+                        .neighbour(a, kernel.support_radius())
+                        .map(|k| {
+                            let r = a.position - k.position;
+                            let gradient = kernel.gradient(r);
+                            boundary.volume()[k.id]
+                                * 2.
+                                * conductivity
+                                * (a.temperature - temperature)
+                                * falloff(r, gradient, a.kernel_radius)
+                        })
+                        .sum::<Real>()
+                });
+                (fluid + solid) / (a.density * self.heat_capacity[a.material])
+            })
+            .collect()
+    }
+
+    /// Advance the temperature of the active particles by `dt` with forward Euler.
+    pub fn update_temperature(&self, space: &mut Space, boundary: Option<&Boundary<T>>, dt: Real) {
+        let _scope = profiler::scope("Conduction::update_temperature");
+        let rate = self.temperature_rate(space, boundary);
+        space
+            .active_particles_mut()
+            .zip(rate)
+            .for_each(|(p, rate)| p.temperature += rate * dt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::CubicSpline;
+    use crate::model::material::Material;
+
+    fn lattice(h: Real, side: isize) -> Vec<Particle> {
+        let mut particles = init_setup::create_cube(1., side, Vector::ZERO, 1., h);
+        particles.iter_mut().for_each(|p| p.density = 1.);
+        particles
+    }
+
+    fn water() -> Conduction<CubicSpline> {
+        let water = Material::water();
+        Conduction::new(
+            vec![water.thermal_conductivity()],
+            vec![water.specific_heat()],
+        )
+    }
+
+    // `T = x^2` has `lap T = 2` everywhere, so `dT / dt = 2 k / (rho c)` inside
+    #[test]
+    fn rate_of_parabola() {
+        let h = 1.3;
+        let mut particles = lattice(h, 15);
+        particles
+            .iter_mut()
+            .for_each(|p| p.temperature = p.position.x.powi(2));
+        let space = Space::new(h, particles);
+        let model = water();
+        let expected = 2. * model.diffusivity(1.);
+
+        let mut count = 0;
+        for (p, rate) in space
+            .active_particles()
+            .zip(model.temperature_rate(&space, None))
+        {
+            if p.position.abs().max_element() < 7. - 2. * h {
+                assert!(
+                    (rate - expected).abs() <= 0.05 * expected,
+                    "{rate} != {expected} at {:?}",
+                    p.position
+                );
+                count += 1;
+            }
+        }
+        assert!(count > 0);
+    }
+
+    // a hot oil block against cold water keeps its heat, `sum m c T`, and the temperature
+    // stays between the two it started with
+    #[test]
+    fn heat_is_conserved() {
+        let h = 1.3;
+        let mut particles = lattice(h, 8);
+        particles
+            .iter_mut()
+            .filter(|p| p.position.x > 0.)
+            .for_each(|p| {
+                p.material = 1;
+                p.temperature = 80.;
+            });
+        let mut space = Space::new(h, particles);
+        let (water, oil) = (Material::water(), Material::oil());
+        let model = Conduction::<CubicSpline>::new(
+            vec![water.thermal_conductivity(), oil.thermal_conductivity()],
+            vec![water.specific_heat(), oil.specific_heat()],
+        );
+        let heat = |space: &Space| {
+            space
+                .particles()
+                .map(|p| p.mass * model.heat_capacity[p.material] * p.temperature)
+                .sum::<Real>()
+        };
+        let before = heat(&space);
+
+        // close to the stable limit, so the heat spreads within a few steps
+        let dt = 0.1 * h * h / model.diffusivity(1.);
+        (0..20).for_each(|_| model.update_temperature(&mut space, None, dt));
+
+        assert!((heat(&space) - before).abs() <= 1e-4 * before);
+        assert!(space
+            .particles()
+            .all(|p| (20. ..=80.).contains(&p.temperature)));
+        let (cold, hot) = space
+            .particles()
+            .fold((20., 80.), |(cold, hot), p| match p.material {
+                0 => (p.temperature.max(cold), hot),
+                _ => (cold, p.temperature.min(hot)),
+            });
+        assert!(cold > 20. && hot < 80., "{cold} {hot}");
+    }
+
+    // a plate held hot warms the fluid resting on it, and only the layers it reaches
+    #[test]
+    fn wall_temperature() {
+        let h = 1.3;
+        let particles = lattice(h, 7)
+            .into_iter()
+            .map(|p| Particle {
+                position: p.position + Vector::Z * 4.,
+                ..p
+            })
+            .collect();
+        let space = Space::new(h, particles);
+        let plate = init_setup::create_plate(1., 11, Vector::ZERO, 1., h);
+        let boundary = Boundary::<CubicSpline>::new(plate, 1., 1.);
+        let model = water();
+
+        let adiabatic = model.temperature_rate(&space, Some(&boundary));
+        assert!(adiabatic.iter().all(|&rate| rate == 0.));
+
+        let boundary = boundary.with_temperature(80.);
+        for (p, rate) in space
+            .active_particles()
+            .zip(model.temperature_rate(&space, Some(&boundary)))
+        {
+            match p.position.z {
+                z if z <= 1. => assert!(rate > 0., "{rate} at {:?}", p.position),
+                z if z >= 3. => assert_eq!(rate, 0.),
+                _ => {}
+            }
+        }
+    }
+}
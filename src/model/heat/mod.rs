@@ -0,0 +1,5 @@
+mod conduction;
+mod phase_change;
+
+pub use conduction::Conduction;
+pub use phase_change::PhaseChange;
@@ -0,0 +1,123 @@
+use crate::profiler;
+use crate::real::*;
+use crate::util_3d::*;
+
+/// Melting and solidification with the enthalpy method. Every particle holds
+/// `e = c (T - T_m) + L f` per unit mass, `f` its liquid fraction. Conduction only moves
+/// `T`, so afterwards `e` is shared out again: below zero the particle is solid and colder
+/// than its melting point, above `L` it is liquid and warmer, and in between it stays at the
+/// melting point while `f` takes up the heat.
+///
+/// A particle counts as solid below half melted, see `Particle::is_solid`. Solids are rigid
+/// and stay where they froze.
+#[derive(Debug)]
+pub struct PhaseChange {
+    /// `(melting point, latent heat in erg/g)` by `Particle::material`, `None` never changes
+    /// phase.
+    melting: Vec<Option<(Real, Real)>>,
+    /// In erg/(g K), by `Particle::material`.
+    heat_capacity: Vec<Real>,
+}
+
+impl PhaseChange {
+    pub fn new(melting: Vec<Option<(Real, Real)>>, heat_capacity: Vec<Real>) -> Self {
+        assert_eq!(melting.len(), heat_capacity.len());
+        assert!(melting.iter().flatten().all(|&(_, latent)| latent > 0.));
+        assert!(heat_capacity.iter().all(|&c| c > 0.));
+        Self {
+            melting,
+            heat_capacity,
+        }
+    }
+
+    /// Liquid fraction of a particle that starts at `temperature`.
+    pub fn liquid_fraction(&self, material: usize, temperature: Real) -> Real {
+        match self.melting[material] {
+            Some((melting_point, _)) if temperature < melting_point => 0.,
+            _ => 1.,
+        }
+    }
+
+    /// Share the heat of every active particle between its temperature and its liquid
+    /// fraction. A particle that freezes stops where it is.
+    pub fn update_phase(&self, space: &mut Space) {
+        let _scope = profiler::scope("PhaseChange::update_phase");
+        space.active_particles_mut().for_each(|p| {
+            let Some((melting_point, latent)) = self.melting[p.material] else {
+                return;
+            };
+            let c = self.heat_capacity[p.material];
+            let was_solid = p.is_solid();
+            let enthalpy = c * (p.temperature - melting_point) + latent * p.liquid_fraction;
+            (p.temperature, p.liquid_fraction) = match enthalpy {
+                e if e < 0. => (melting_point + e / c, 0.),
+                e if e > latent => (melting_point + (e - latent) / c, 1.),
+                e => (melting_point, e / latent),
+            };
+            if p.is_solid() && !was_solid {
+                p.velocity = Vector::ZERO;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::material::Material;
+
+    fn water() -> PhaseChange {
+        let water = Material::water();
+        PhaseChange::new(
+            vec![water
+                .melting_point
+                .map(|t| (t, water.latent_heat_of_melting()))],
+            vec![water.specific_heat()],
+        )
+    }
+
+    // ice heated at a steady rate warms to the melting point, stays there until the latent
+    // heat is in and then warms again
+    #[test]
+    fn melting_holds_temperature() {
+        let model = water();
+        let mut particles = init_setup::create_cube(1., 1, Vector::ZERO, 1., 1.);
+        particles[0].temperature = -10.;
+        particles[0].liquid_fraction = model.liquid_fraction(0, -10.);
+        let mut space = Space::new(1., particles);
+        assert!(space.particles().all(Particle::is_solid));
+
+        // latent heat of water is worth 80 K, so 100 K of heat ends at 10 degrees
+        let mut plateau = 0;
+        for _ in 0..100 {
+            space.particles_mut().for_each(|p| p.temperature += 1.);
+            model.update_phase(&mut space);
+            let p = space.particles().next().unwrap();
+            if p.liquid_fraction > 0. && p.liquid_fraction < 1. {
+                assert_eq!(p.temperature, 0.);
+                plateau += 1;
+            }
+        }
+        assert!((79..=81).contains(&plateau), "{plateau}");
+        let p = space.particles().next().unwrap();
+        assert!((p.temperature - 10.).abs() <= 0.5, "{}", p.temperature);
+        assert!(!p.is_solid());
+    }
+
+    // water cooled past freezing stops moving
+    #[test]
+    fn freezing_stops_particle() {
+        let model = water();
+        let mut particles = init_setup::create_cube(1., 1, Vector::ZERO, 1., 1.);
+        particles[0].velocity = Vector::X;
+        particles[0].temperature = -100.;
+        let mut space = Space::new(1., particles);
+
+        model.update_phase(&mut space);
+        let p = space.particles().next().unwrap();
+        assert!(p.is_solid());
+        assert_eq!(p.velocity, Vector::ZERO);
+        // what the latent heat could not make up for
+        assert!(p.temperature < 0. && p.temperature > -100.);
+    }
+}
@@ -0,0 +1,121 @@
+use uom::si::{
+    available_energy, dynamic_viscosity, mass_density, specific_heat_capacity, thermal_conductivity,
+};
+
+use crate::model::viscosity::Rheology;
+use crate::real::*;
+
+/// Constants of one fluid, every particle points at its own with `Particle::material`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    pub density: si::MassDensity,
+    /// Physical viscosity, `None` leaves the fluid to the artificial one.
+    pub viscosity: Option<Rheology>,
+    /// Surface tension against air in dyn/cm, used by the csf surface tension.
+    pub surface_tension: Real,
+    /// Exponent of the Tait equation of state.
+    pub gamma: i32,
+    pub conductivity: si::ThermalConductivity,
+    /// The same in both phases.
+    pub heat_capacity: si::SpecificHeatCapacity,
+    /// In degrees Celsius, `None` for a fluid that never freezes.
+    pub melting_point: Option<Real>,
+    pub latent_heat: si::AvailableEnergy,
+}
+
+impl Material {
+    /// At the particle sizes used here the artificial viscosity is far above the 0.01 P of
+    /// water, so no physical one is added.
+    pub fn water() -> Self {
+        Self {
+            density: si::MassDensity::new::<mass_density::kilogram_per_cubic_meter>(1000.),
+            viscosity: None,
+            surface_tension: 72.,
+            gamma: 7,
+            conductivity: si::ThermalConductivity::new::<thermal_conductivity::watt_per_meter_kelvin>(
+                0.6,
+            ),
+            heat_capacity: si::SpecificHeatCapacity::new::<
+                specific_heat_capacity::joule_per_kilogram_kelvin,
+            >(4186.),
+            melting_point: Some(0.),
+            latent_heat: si::AvailableEnergy::new::<available_energy::kilojoule_per_kilogram>(334.),
+        }
+    }
+
+    /// Light machine oil, floats on water.
+    pub fn oil() -> Self {
+        Self {
+            density: si::MassDensity::new::<mass_density::kilogram_per_cubic_meter>(880.),
+            viscosity: Some(Rheology::newtonian(si::DynamicViscosity::new::<
+                dynamic_viscosity::poise,
+            >(1.))),
+            surface_tension: 30.,
+            gamma: 7,
+            conductivity: si::ThermalConductivity::new::<thermal_conductivity::watt_per_meter_kelvin>(
+                0.15,
+            ),
+            heat_capacity: si::SpecificHeatCapacity::new::<
+                specific_heat_capacity::joule_per_kilogram_kelvin,
+            >(1900.),
+            melting_point: None,
+            latent_heat: si::AvailableEnergy::new::<available_energy::kilojoule_per_kilogram>(0.),
+        }
+    }
+
+    /// Twice as dense as water and otherwise the same, sinks through it.
+    pub fn heavy() -> Self {
+        Self {
+            density: si::MassDensity::new::<mass_density::kilogram_per_cubic_meter>(2000.),
+            ..Self::water()
+        }
+    }
+
+    /// In g/cm^3.
+    pub fn rest_density(&self) -> Real {
+        self.density
+            .get::<mass_density::gram_per_cubic_centimeter>()
+    }
+
+    /// In erg/(s cm K).
+    pub fn thermal_conductivity(&self) -> Real {
+        // g m / (s^3 K) is 100 erg/(s cm K)
+        100. * self
+            .conductivity
+            .get::<thermal_conductivity::gram_meter_per_second_cubed_kelvin>()
+    }
+
+    /// In erg/(g K).
+    pub fn specific_heat(&self) -> Real {
+        self.heat_capacity
+            .get::<specific_heat_capacity::square_centimeter_per_second_squared_kelvin>()
+    }
+
+    /// Heat of melting in erg/g.
+    pub fn latent_heat_of_melting(&self) -> Real {
+        // J/kg is 10^4 erg/g
+        1e4 * self
+            .latent_heat
+            .get::<available_energy::joule_per_kilogram>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rest_density_in_grams() {
+        assert!((Material::water().rest_density() - 1.).abs() <= 1e-6);
+        assert!(Material::oil().rest_density() < Material::water().rest_density());
+        assert!((Material::heavy().rest_density() - 2.).abs() <= 1e-6);
+    }
+
+    #[test]
+    fn thermal_constants_in_cgs() {
+        let water = Material::water();
+        assert!((water.thermal_conductivity() / 0.6e5 - 1.).abs() <= 1e-5);
+        assert!((water.specific_heat() / 4.186e7 - 1.).abs() <= 1e-5);
+        assert!((water.latent_heat_of_melting() / 3.34e9 - 1.).abs() <= 1e-5);
+    }
+}
@@ -1,4 +1,9 @@
+pub mod boundary;
 pub mod density;
+pub mod heat;
+pub mod material;
 pub mod pressure;
 pub mod surface_tension;
 pub mod viscosity;
+pub mod vorticity;
+pub mod xsph;
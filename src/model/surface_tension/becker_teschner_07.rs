@@ -1,35 +1,36 @@
 use std::marker::PhantomData;
 
-use itertools::Itertools;
-use macroquad::prelude::*;
 use rayon::prelude::*;
 
 use crate::kernel;
-use crate::kernel::Kernel;
+use crate::profiler;
+use crate::real::*;
 use crate::util_3d::*;
 
 #[derive(Debug)]
-pub struct BeakerTeschner07<T: kernel::Kernel + Sync + Send> {
+pub struct BeckerTeschner07<T: kernel::Kernel + Sync + Send> {
     _kernel: PhantomData<T>,
 }
 
-impl<T: kernel::Kernel + Sync + Send> BeakerTeschner07<T> {
+impl<T: kernel::Kernel + Sync + Send> BeckerTeschner07<T> {
     pub fn new() -> Self {
         Self {
             _kernel: PhantomData::default(),
         }
     }
 
-    pub fn accelration(&self, space: &Space) -> Vec<Vec3> {
+    /// One entry per `space.active_particles()`.
+    pub fn accelration(&self, space: &Space) -> Vec<Vector> {
+        let _scope = profiler::scope("BeckerTeschner07::accelration");
         space
-            .particles()
+            .par_active_particles()
             .map(|a| {
                 let kernel = T::new(a.kernel_radius);
                 let others = space.neighbour(a, kernel.support_radius());
 
-                let mut sum = Vec3::ZERO;
-                let mut color_field_gradient = Vec3::ZERO;
-                let mut color_field_laplacian = Vec3::ZERO;
+                let mut sum = Vector::ZERO;
+                let mut color_field_gradient = Vector::ZERO;
+                let mut color_field_laplacian = Vector::ZERO;
                 others.for_each(|b| {
                     let r = a.position - b.position;
                     sum += b.mass * kernel.function(r) * r;
@@ -49,7 +50,7 @@ mod tests {
     use self::{init_setup::create_sphere, kernel::CubicSpline};
 
     use super::*;
-    use crate::model::density::Density;
+    use crate::model::density::{Density, SmoothingLength};
 
     // surface_tension should all point to the (0.,0.,0.)
     #[test]
@@ -57,9 +58,9 @@ mod tests {
         let h = 5.;
         let mass = 1.;
 
-        let density_model = Density::<CubicSpline>::new();
-        let surface_tension_model = BeakerTeschner07::<CubicSpline>::new();
-        let particle = create_sphere(mass, 1., 50, Vec3::ZERO, h);
+        let density_model = Density::<CubicSpline>::new(SmoothingLength::default());
+        let surface_tension_model = BeckerTeschner07::<CubicSpline>::new();
+        let particle = create_sphere(mass, 1., 50, Vector::ZERO, h);
         let mut space = Space::new(h, particle);
 
         density_model.update_density(&mut space);
@@ -1,5 +1,40 @@
+mod akinci13;
 mod becker_teschner_07;
-// mod surface_tension;
+mod csf;
 
-pub use becker_teschner_07::BeakerTeschner07;
-// pub use surface_tension::SurfaceTension;
+pub use akinci13::Akinci13;
+pub use becker_teschner_07::BeckerTeschner07;
+pub use csf::Csf;
+
+use crate::real::Real;
+
+/// Which surface tension acts between fluid particles.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SurfaceTensionMethod {
+    #[default]
+    BeckerTeschner07,
+    /// Cohesion and curvature, plus adhesion next to a solid (Akinci et al. 2013).
+    Akinci13 { tension: Real, adhesion: Real },
+    /// Continuum surface force, `coefficient` in dyn/cm and `threshold` on `h |grad c|`.
+    Csf { coefficient: Real, threshold: Real },
+}
+
+impl SurfaceTensionMethod {
+    /// `becker_teschner_07`, `akinci13`, whose tension is kept low because the curvature
+    /// term is not conservative and stirs up a fluid as soft as the default one, or `csf`
+    /// with the surface tension of water.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "becker_teschner_07" => Some(SurfaceTensionMethod::BeckerTeschner07),
+            "akinci13" => Some(SurfaceTensionMethod::Akinci13 {
+                tension: 0.005,
+                adhesion: 1.,
+            }),
+            "csf" => Some(SurfaceTensionMethod::Csf {
+                coefficient: 72.,
+                threshold: 0.3,
+            }),
+            _ => None,
+        }
+    }
+}
@@ -1,69 +1,350 @@
 use std::marker::PhantomData;
 
-use itertools::Itertools;
-use macroquad::prelude::*;
 use rayon::prelude::*;
 
-use crate::kernel;
+use crate::kernel::{self, gather, Lanes, SimdKernel, VectorLanes};
+use crate::profiler;
+use crate::real::*;
 use crate::util_3d::*;
+use wide::CmpGt;
+
+use super::rheology::{curl, divergence, velocity_gradient};
+
+/// How the viscosity coefficient `alpha` of each particle evolves.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum AlphaSwitch {
+    /// The same `alpha` everywhere, all the time.
+    #[default]
+    Constant,
+    /// Morris & Monaghan 1997, `d alpha / dt = -(alpha - min) / tau + max(-div v, 0) (max - alpha)`
+    /// with `tau = h / (decay c)`.
+    MorrisMonaghan { min: Real, max: Real, decay: Real },
+    /// Cullen & Dehnen 2010, jumps to the level set by the rate at which `div v` falls,
+    /// then decays towards it with `tau = h / (decay c)`.
+    CullenDehnen { min: Real, max: Real, decay: Real },
+}
+
+impl AlphaSwitch {
+    /// `constant`, `morris_monaghan` or `cullen_dehnen` with the usual coefficients.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "constant" => Some(AlphaSwitch::Constant),
+            "morris_monaghan" => Some(AlphaSwitch::MorrisMonaghan {
+                min: 0.01,
+                max: 1.,
+                decay: 0.2,
+            }),
+            "cullen_dehnen" => Some(AlphaSwitch::CullenDehnen {
+                min: 0.,
+                max: 1.,
+                decay: 0.05,
+            }),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Artificial<T: kernel::Kernel> {
-    alpha: f32,
-    speed_sound: f32,
+    alpha: Real,
+    speed_sound: Real,
+    switch: AlphaSwitch,
+    /// Scale every pair by the Balsara 1995 factor, which is small in shear flow.
+    balsara: bool,
     _kernel: PhantomData<T>,
 }
 
 impl<T: kernel::Kernel + Sync + Send> Artificial<T> {
-    pub fn new(alpha: f32, speed_sound: f32) -> Self {
+    pub fn new(alpha: Real, speed_sound: Real) -> Self {
         assert!(speed_sound > 0.0);
         // alpha between 0.08 and 0.5
         Self {
             alpha,
             speed_sound,
+            switch: AlphaSwitch::Constant,
+            balsara: false,
             _kernel: PhantomData::default(),
         }
     }
 
-    pub fn accelration(&self, space: &Space) -> Vec<Vec3> {
+    /// Per-particle `alpha` driven by `switch`, which replaces the constant one.
+    pub fn with_switch(self, switch: AlphaSwitch, balsara: bool) -> Self {
+        Self {
+            switch,
+            balsara,
+            ..self
+        }
+    }
+
+    /// Kinematic viscosity the artificial term is equivalent to in 3D, `alpha h c / 10`,
+    /// with the largest `alpha` the switch allows.
+    pub fn kinematic_viscosity(&self, h: Real) -> Real {
+        let alpha = match self.switch {
+            AlphaSwitch::Constant => self.alpha,
+            AlphaSwitch::MorrisMonaghan { max, .. } | AlphaSwitch::CullenDehnen { max, .. } => max,
+        };
+        alpha * h * self.speed_sound / 10.
+    }
+
+    /// Start every particle at the quiet level of the switch.
+    pub fn reset_alpha(&self, space: &mut Space) {
+        let alpha = match self.switch {
+            AlphaSwitch::Constant => self.alpha,
+            AlphaSwitch::MorrisMonaghan { min, .. } | AlphaSwitch::CullenDehnen { min, .. } => min,
+        };
+        space.particles_mut().for_each(|p| {
+            p.alpha = alpha;
+            p.velocity_divergence = 0.;
+        });
+    }
+
+    /// Advance the `alpha` of the active particles over `dt`, nothing to do for a constant one.
+    pub fn update_alpha(&self, space: &mut Space, dt: Real) {
+        if self.switch == AlphaSwitch::Constant {
+            return;
+        }
+        let _scope = profiler::scope("Artificial::update_alpha");
+        self.update_balsara(space);
+        let next = space
+            .par_active_particles()
+            .map(|a| {
+                let divergence = divergence(velocity_gradient::<T>(space, a));
+                let tau = |decay: Real| a.kernel_radius / (decay * self.speed_sound);
+                let alpha = match self.switch {
+                    AlphaSwitch::Constant => a.alpha,
+                    AlphaSwitch::MorrisMonaghan { min, max, decay } => {
+                        // backward Euler, stays within [min, max] at any dt
+                        let source = (-divergence).max(0.);
+                        (a.alpha + dt * (min / tau(decay) + source * max))
+                            / (1. + dt * (1. / tau(decay) + source))
+                    }
+                    AlphaSwitch::CullenDehnen { min, max, decay } => {
+                        let falling = (a.velocity_divergence - divergence) / dt;
+                        let source = a.balsara * falling.max(0.);
+                        let h2 = a.kernel_radius.powi(2);
+                        let local = max * h2 * source / (self.speed_sound.powi(2) + h2 * source);
+                        let alpha = match a.alpha < local {
+                            true => local,
+                            false => local + (a.alpha - local) * (-dt / tau(decay)).exp(),
+                        };
+                        alpha.max(min)
+                    }
+                };
+                (alpha, divergence)
+            })
+            .collect::<Vec<_>>();
+
         space
-            .particles()
+            .active_particles_mut()
+            .zip(next)
+            .for_each(|(p, (alpha, divergence))| {
+                p.alpha = alpha;
+                p.velocity_divergence = divergence;
+            });
+    }
+
+    /// Set `|div v| / (|div v| + |curl v| + 1e-4 c / h)` on the active particles,
+    /// nothing to do without the limiter.
+    pub fn update_balsara(&self, space: &mut Space) {
+        if !self.balsara {
+            return;
+        }
+        let _scope = profiler::scope("Artificial::update_balsara");
+        let factor = space
+            .par_active_particles()
+            .map(|a| {
+                let gradient = velocity_gradient::<T>(space, a);
+                let divergence = divergence(gradient).abs();
+                let floor = 1e-4 * self.speed_sound / a.kernel_radius;
+                divergence / (divergence + curl(gradient).length() + floor)
+            })
+            .collect::<Vec<_>>();
+        space
+            .active_particles_mut()
+            .zip(factor)
+            .for_each(|(p, f)| p.balsara = f);
+    }
+
+    // force on `a` from `b` is `r` times this, zero while they move apart (Monaghan 1992).
+    // Shared by every loop below so that they sum the same thing.
+    fn pair<S: Lanes>(&self, a: Side<S>, b: Side<S>, r_dot_v: S, r_squared: S) -> S {
+        let half = S::splat(0.5);
+        let alpha = match self.switch {
+            AlphaSwitch::Constant => S::splat(self.alpha),
+            _ => (a.alpha + b.alpha) * half,
+        };
+        let alpha = alpha * (a.balsara + b.balsara) * half;
+        let h = (a.kernel_radius + b.kernel_radius) * half;
+        let denominator = r_squared + S::splat(0.01) * h * h;
+        let constant = -(S::splat(2. * self.speed_sound) * alpha * h) / (a.density + b.density);
+        let gradient = (a.gradient + b.gradient) * half;
+        r_dot_v.if_negative(-(b.mass * gradient * constant * r_dot_v / denominator))
+    }
+
+    /// One entry per `space.active_particles()`, call `update_balsara` first.
+    /// The kernel gradient is averaged over both particles so pair forces are equal and opposite.
+    pub fn accelration(&self, space: &Space) -> Vec<Vector> {
+        let _scope = profiler::scope("Artificial::accelration");
+        let radius = T::new(space.max_kernel_radius()).support_radius();
+        space
+            .par_active_particles()
             .map(|a| {
                 let kernel = T::new(a.kernel_radius);
-                let others = space.neighbour(a, kernel.support_radius());
+                let others = space.neighbour(a, radius);
                 others
                     .map(|b| {
                         let r = a.position - b.position;
+                        let length = r.length();
+                        let side_a = Side::of(a, kernel.gradient_scale(length));
+                        let side_b = Side::of(b, T::new(b.kernel_radius).gradient_scale(length));
                         let v = a.velocity - b.velocity;
-                        let numerator = r.dot(v);
-                        if numerator >= 0. {
-                            return Vec3::ZERO;
-                        }
-                        let h = (a.kernel_radius + b.kernel_radius) / 2.;
-                        let denominator = r.length_squared() + 0.01 * h.powi(2);
-                        let constant =
-                            -(2. * self.alpha * h * self.speed_sound) / (a.density + b.density);
-                        b.mass * kernel.gradient(r) * constant * numerator / denominator
+                        r * self.pair(side_a, side_b, r.dot(v), r.length_squared())
+                    })
+                    .fold(Vector::ZERO, |a, b| a + b)
+            })
+            .collect::<Vec<_>>()
+    }
+
+    /// Same as `accelration`, over a snapshot with precomputed neighbour lists.
+    pub fn accelration_soa(&self, soa: &ParticleSoa, neighbours: &NeighbourList) -> Vec<Vector> {
+        let _scope = profiler::scope("Artificial::accelration_soa");
+        (0..soa.len())
+            .into_par_iter()
+            .map(|a| {
+                let kernel = T::new(soa.kernel_radius[a]);
+                neighbours
+                    .of(a)
+                    .iter()
+                    .map(|&b| {
+                        let r = soa.position[a] - soa.position[b];
+                        let length = r.length();
+                        let side_a = Side::at(soa, a, kernel.gradient_scale(length));
+                        let gradient_b = T::new(soa.kernel_radius[b]).gradient_scale(length);
+                        let side_b = Side::at(soa, b, gradient_b);
+                        let v = soa.velocity[a] - soa.velocity[b];
+                        r * self.pair(side_a, side_b, r.dot(v), r.length_squared())
+                    })
+                    .fold(Vector::ZERO, |a, b| a + b)
+            })
+            .collect::<Vec<_>>()
+    }
+
+    /// Same as `accelration_soa`, `LANES` neighbours at a time.
+    pub fn accelration_simd(&self, soa: &ParticleSoa, neighbours: &NeighbourList) -> Vec<Vector>
+    where
+        T: SimdKernel,
+    {
+        let _scope = profiler::scope("Artificial::accelration_simd");
+        (0..soa.len())
+            .into_par_iter()
+            .map(|a| {
+                let kernel = T::new(soa.kernel_radius[a]);
+                let position_a = VectorLanes::splat(soa.position[a]);
+                let velocity_a = VectorLanes::splat(soa.velocity[a]);
+                let h_a = RealLanes::splat(soa.kernel_radius[a]);
+                let side_a = Side::at(soa, a, 0.);
+                neighbours
+                    .of(a)
+                    .chunks(LANES)
+                    .map(|index| {
+                        let r = position_a - VectorLanes::gather(index, &soa.position);
+                        let v = velocity_a - VectorLanes::gather(index, &soa.velocity);
+                        let length = r.length();
+                        // unused lanes borrow h_a, their mass is zero
+                        let h_b = gather(index, |b| soa.kernel_radius[b]);
+                        let h_b = h_b.cmp_gt(RealLanes::ZERO).blend(h_b, h_a);
+                        let side_a = side_a.splat(kernel.gradient_scale_lanes(length));
+                        let gradient_b = T::gradient_scale_lanes_with(h_b, length);
+                        let side_b = Side::gather(soa, index, h_b, gradient_b);
+                        r * self.pair(side_a, side_b, r.dot(v), r.length_squared())
                     })
-                    .fold(Vec3::ZERO, |a, b| a + b)
-                    * -1.
+                    .fold(Vector::ZERO, |sum, v| sum + v.sum())
             })
             .collect::<Vec<_>>()
     }
 }
 
+// what the pair term needs of one particle, one value or `LANES` of them
+#[derive(Debug, Clone, Copy)]
+struct Side<S> {
+    mass: S,
+    kernel_radius: S,
+    density: S,
+    alpha: S,
+    balsara: S,
+    /// `dW/dr / r` with the kernel of this particle.
+    gradient: S,
+}
+
+impl Side<Real> {
+    fn of(p: &Particle, gradient: Real) -> Self {
+        Self {
+            mass: p.mass,
+            kernel_radius: p.kernel_radius,
+            density: p.density,
+            alpha: p.alpha,
+            balsara: p.balsara,
+            gradient,
+        }
+    }
+
+    fn at(soa: &ParticleSoa, i: usize, gradient: Real) -> Self {
+        Self {
+            mass: soa.mass[i],
+            kernel_radius: soa.kernel_radius[i],
+            density: soa.density[i],
+            alpha: soa.alpha[i],
+            balsara: soa.balsara[i],
+            gradient,
+        }
+    }
+
+    fn splat(self, gradient: RealLanes) -> Side<RealLanes> {
+        Side {
+            mass: RealLanes::splat(self.mass),
+            kernel_radius: RealLanes::splat(self.kernel_radius),
+            density: RealLanes::splat(self.density),
+            alpha: RealLanes::splat(self.alpha),
+            balsara: RealLanes::splat(self.balsara),
+            gradient,
+        }
+    }
+}
+
+impl Side<RealLanes> {
+    // unused lanes get zero
+    fn gather(
+        soa: &ParticleSoa,
+        index: &[usize],
+        kernel_radius: RealLanes,
+        gradient: RealLanes,
+    ) -> Self {
+        Self {
+            mass: gather(index, |b| soa.mass[b]),
+            kernel_radius,
+            density: gather(index, |b| soa.density[b]),
+            alpha: gather(index, |b| soa.alpha[b]),
+            balsara: gather(index, |b| soa.balsara[b]),
+            gradient,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{kernel::*, model::density::Density};
+    use crate::{
+        kernel::*,
+        model::density::{Density, SmoothingLength},
+    };
 
     #[test]
     fn direction_check() {
         let h = 5.;
         let mass = 1.;
-        let speed_sound = 10. * ((2. * 9.81 * 0.5) as f32).sqrt();
+        let speed_sound = 10. * ((2. * 9.81 * 0.5) as Real).sqrt();
 
-        let density_model = Density::<CubicSpline>::new();
+        let density_model = Density::<CubicSpline>::new(SmoothingLength::default());
         let viscoity_model = Artificial::<CubicSpline>::new(0.08, speed_sound);
 
         let particle = init_setup::diagonal_test(mass, h);
@@ -73,8 +354,125 @@ mod tests {
         let viscosity = viscoity_model.accelration(&space);
 
         dbg!(&viscosity, &space);
-        assert!(viscosity[1].length() <= f32::EPSILON);
-        assert!(viscosity[0].normalize().dot(Vec3::ONE) <= f32::EPSILON);
-        assert!(viscosity[2].normalize().dot(Vec3::NEG_ONE) <= f32::EPSILON);
+        assert!(viscosity[1].length() <= Real::EPSILON);
+        assert!(viscosity[0].normalize().dot(Vector::ONE) <= Real::EPSILON);
+        assert!(viscosity[2].normalize().dot(Vector::NEG_ONE) <= Real::EPSILON);
+    }
+
+    #[test]
+    fn simd_match_scalar() {
+        let h = 1.3;
+        let mass = 1.;
+        let speed_sound = 10. * ((2. * 9.81 * 0.5) as Real).sqrt();
+
+        let density_model = Density::<CubicSpline>::new(SmoothingLength::default());
+        let switch = AlphaSwitch::from_name("morris_monaghan").unwrap();
+        let viscoity_model =
+            Artificial::<CubicSpline>::new(0.08, speed_sound).with_switch(switch, true);
+        let mut particle = init_setup::create_cube(1., 5, Vector::ZERO, mass, h);
+        particle.iter_mut().for_each(|p| {
+            p.velocity = vector(p.position.y, -p.position.x, 0.5 * p.position.z);
+            p.alpha = 0.1 + 0.05 * p.position.x;
+        });
+        let mut space = Space::new(h, particle);
+
+        density_model.update_density(&mut space);
+        viscoity_model.update_balsara(&mut space);
+        let expect = space.order_by_id(viscoity_model.accelration(&space));
+
+        let soa = space.to_soa();
+        let neighbours = NeighbourList::new::<CubicSpline>(&soa);
+        let scalar = viscoity_model.accelration_soa(&soa, &neighbours);
+        let simd = viscoity_model.accelration_simd(&soa, &neighbours);
+
+        for ((e, a), b) in expect.into_iter().zip(scalar).zip(simd) {
+            let tolerance = 1e-4 * e.length().max(1.);
+            assert!(
+                (e - a).length() <= tolerance,
+                "left: {:?}, right: {:?}",
+                e,
+                a
+            );
+            assert!(
+                (a - b).length() <= tolerance,
+                "left: {:?}, right: {:?}",
+                a,
+                b
+            );
+        }
+    }
+
+    // compression raises alpha towards its maximum, at rest it decays back
+    #[test]
+    fn alpha_switch() {
+        let h = 1.3;
+        let speed_sound = 20.;
+        for name in ["morris_monaghan", "cullen_dehnen"] {
+            let switch = AlphaSwitch::from_name(name).unwrap();
+            let model = Artificial::<CubicSpline>::new(0.5, speed_sound).with_switch(switch, false);
+            let mut particles = init_setup::create_cube(1., 7, Vector::ZERO, 1., h);
+            particles.iter_mut().for_each(|p| p.density = 1.);
+            let mut space = Space::new(h, particles);
+            model.reset_alpha(&mut space);
+            let centre = |space: &Space| {
+                space
+                    .particles()
+                    .min_by(|a, b| a.position.length().total_cmp(&b.position.length()))
+                    .unwrap()
+                    .alpha
+            };
+            let quiet = centre(&space);
+
+            for step in 1..=10 {
+                space
+                    .particles_mut()
+                    .for_each(|p| p.velocity = -p.position * (step as Real * 0.5));
+                model.update_alpha(&mut space, 0.01);
+            }
+            let loud = centre(&space);
+            assert!(loud > quiet + 0.1, "{name}: {quiet} -> {loud}");
+
+            space
+                .particles_mut()
+                .for_each(|p| p.velocity = Vector::ZERO);
+            (0..50).for_each(|_| model.update_alpha(&mut space, 0.1));
+            let calm = centre(&space);
+            assert!(
+                calm - quiet < 0.05 * (loud - quiet),
+                "{name}: {loud} -> {calm}"
+            );
+        }
+    }
+
+    // shear keeps its momentum under the Balsara limiter, compression is still damped
+    #[test]
+    fn balsara_limiter() {
+        let h = 1.3;
+        let constant = Artificial::<CubicSpline>::new(0.5, 20.);
+        let limited =
+            Artificial::<CubicSpline>::new(0.5, 20.).with_switch(AlphaSwitch::Constant, true);
+        let particles = init_setup::create_cube(1., 15, Vector::ZERO, 1., h);
+        let norm = |model: &Artificial<CubicSpline>, flow: &dyn Fn(Vector) -> Vector| {
+            let mut space = Space::new(h, particles.clone());
+            space.particles_mut().for_each(|p| {
+                p.density = 1.;
+                p.velocity = flow(p.position);
+            });
+            model.update_balsara(&mut space);
+            model
+                .accelration(&space)
+                .iter()
+                .zip(space.active_particles())
+                .filter(|(_, p)| p.position.abs().max_element() < 7. - 4. * h)
+                .map(|(a, _)| a.length())
+                .sum::<Real>()
+        };
+
+        let shear = |x: Vector| Vector::X * x.y.powi(2);
+        let ratio = norm(&limited, &shear) / norm(&constant, &shear);
+        assert!(ratio < 0.01, "{ratio}");
+        let compression = |x: Vector| -x * (1. + x.length_squared() / 10.);
+        let ratio = norm(&limited, &compression) / norm(&constant, &compression);
+        assert!((ratio - 1.).abs() < 0.01, "{ratio}");
     }
 }
@@ -0,0 +1,253 @@
+use std::marker::PhantomData;
+
+use crate::kernel;
+use crate::profiler;
+use crate::real::*;
+use crate::util_3d::*;
+
+/// Settings of the implicit viscosity solve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImplicitSolver {
+    /// Stop once the residual is this fraction of the right hand side.
+    pub tolerance: Real,
+    pub max_iterations: usize,
+}
+
+impl Default for ImplicitSolver {
+    fn default() -> Self {
+        Self {
+            tolerance: 1e-4,
+            max_iterations: 100,
+        }
+    }
+}
+
+/// Outcome of the last solve.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Convergence {
+    pub iterations: usize,
+    /// Relative residual `|b - A v| / |b|`.
+    pub residual: Real,
+    pub converged: bool,
+}
+
+/// Backward Euler viscosity step (Weiler et al. 2018), unconditionally stable so the
+/// time step no longer shrinks with the viscosity. Each particle uses its `viscosity`.
+#[derive(Debug)]
+pub struct Implicit<T: kernel::Kernel> {
+    solver: ImplicitSolver,
+    _kernel: PhantomData<T>,
+}
+
+// a neighbour of a particle and the 3x3 block coupling them
+type Coupling = Vec<(usize, Matrix3)>;
+
+impl<T: kernel::Kernel + Sync + Send> Implicit<T> {
+    pub fn new(solver: ImplicitSolver) -> Self {
+        assert!(solver.tolerance > 0.);
+        Self {
+            solver,
+            _kernel: PhantomData,
+        }
+    }
+
+    /// Solve `v_a - dt sum_b K_ab (v_a - v_b) = v_a^0` and write `v` to the active particles.
+    /// Rows are scaled by `m_a` so the system is symmetric and solved with block Jacobi
+    /// preconditioned conjugate gradient.
+    pub fn solve(&self, space: &mut Space, dt: Real) -> Convergence {
+        let _scope = profiler::scope("Implicit::solve");
+        let coupling = self.coupling(space);
+        let (mass, velocity): (Vec<_>, Vec<_>) = space
+            .active_particles()
+            .map(|p| (p.mass, p.velocity))
+            .unzip();
+
+        let apply = |x: &[Vector]| -> Vec<Vector> {
+            coupling
+                .iter()
+                .enumerate()
+                .map(|(a, pairs)| {
+                    let sum = pairs
+                        .iter()
+                        .fold(Vector::ZERO, |s, (b, k)| s + *k * (x[a] - x[*b]));
+                    mass[a] * (x[a] - dt * sum)
+                })
+                .collect()
+        };
+        let preconditioner = coupling
+            .iter()
+            .enumerate()
+            .map(|(a, pairs)| {
+                let sum = pairs.iter().fold(Matrix3::ZERO, |s, (_, k)| s + *k);
+                (mass[a] * (Matrix3::IDENTITY - sum * dt)).inverse()
+            })
+            .collect::<Vec<_>>();
+        let precondition = |r: &[Vector]| -> Vec<Vector> {
+            preconditioner.iter().zip(r).map(|(m, r)| *m * *r).collect()
+        };
+
+        let rhs = mass
+            .iter()
+            .zip(&velocity)
+            .map(|(m, v)| *v * *m)
+            .collect::<Vec<_>>();
+        let norm = dot(&rhs, &rhs).sqrt();
+        let mut x = velocity;
+        let mut r = sub(&rhs, &apply(&x));
+        let mut z = precondition(&r);
+        let mut p = z.clone();
+        let mut rz = dot(&r, &z);
+        let mut convergence = Convergence::default();
+        loop {
+            convergence.residual = match norm {
+                n if n > 0. => dot(&r, &r).sqrt() / n,
+                _ => 0.,
+            };
+            convergence.converged = convergence.residual <= self.solver.tolerance;
+            if convergence.converged || convergence.iterations >= self.solver.max_iterations {
+                break;
+            }
+
+            let ap = apply(&p);
+            let alpha = rz / dot(&p, &ap);
+            x.iter_mut().zip(&p).for_each(|(x, p)| *x += *p * alpha);
+            r.iter_mut().zip(&ap).for_each(|(r, ap)| *r -= *ap * alpha);
+            z = precondition(&r);
+            let rz_next = dot(&r, &z);
+            let beta = rz_next / rz;
+            rz = rz_next;
+            p.iter_mut().zip(&z).for_each(|(p, z)| *p = *z + *p * beta);
+            convergence.iterations += 1;
+        }
+
+        space
+            .active_particles_mut()
+            .zip(x)
+            .for_each(|(p, v)| p.velocity = v);
+        convergence
+    }
+
+    // K_ab = 2 (d + 2) m_b mu_ab / (rho_a rho_b) grad W_ab x_ab^T / (r^2 + 0.01 h^2),
+    // indexed by position in `space.active_particles()`
+    fn coupling(&self, space: &Space) -> Vec<Coupling> {
+        let index = {
+            let mut index = vec![None; space.len()];
+            space
+                .active_particles()
+                .enumerate()
+                .for_each(|(i, p)| index[p.id] = Some(i));
+            index
+        };
+        let radius = T::new(space.max_kernel_radius()).support_radius();
+        space
+            .active_particles()
+            .map(|a| {
+                space
+                    .neighbour(a, radius)
+                    .filter(|b| b.id != a.id)
+                    .filter_map(|b| {
+                        let r = a.position - b.position;
+                        let gradient = (T::new(a.kernel_radius).gradient(r)
+                            + T::new(b.kernel_radius).gradient(r))
+                            / 2.;
+                        if gradient == Vector::ZERO {
+                            return None;
+                        }
+                        let h = (a.kernel_radius + b.kernel_radius) / 2.;
+                        let viscosity = (a.viscosity + b.viscosity) / 2.;
+                        let scale = 10. * b.mass * viscosity
+                            / (a.density * b.density * (r.length_squared() + 0.01 * h * h));
+                        let block =
+                            Matrix3::from_cols(gradient * r.x, gradient * r.y, gradient * r.z);
+                        Some((index[b.id]?, block * scale))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+fn dot(x: &[Vector], y: &[Vector]) -> Real {
+    x.iter().zip(y).map(|(x, y)| x.dot(*y)).sum()
+}
+
+fn sub(x: &[Vector], y: &[Vector]) -> Vec<Vector> {
+    x.iter().zip(y).map(|(x, y)| *x - *y).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::CubicSpline;
+    use macroquad::rand::{gen_range, srand};
+
+    fn viscous_cube(viscosity: Real) -> Space {
+        let h = 1.3;
+        srand(3);
+        let mut particles = init_setup::create_cube(1., 8, Vector::ZERO, 1., h);
+        particles.iter_mut().for_each(|p| {
+            p.density = 1.;
+            p.viscosity = viscosity;
+            p.velocity = vector(gen_range(-1., 1.), gen_range(-1., 1.), gen_range(-1., 1.));
+        });
+        Space::new(h, particles)
+    }
+
+    // the solution satisfies the system it was asked to solve
+    #[test]
+    fn converge_to_tolerance() {
+        let (dt, tolerance) = (0.01, 1e-6);
+        let model = Implicit::<CubicSpline>::new(ImplicitSolver {
+            tolerance,
+            max_iterations: 200,
+        });
+        let mut space = viscous_cube(10.);
+        let initial = space.collect_by_id(|p| p.velocity);
+
+        let convergence = model.solve(&mut space, dt);
+        assert!(convergence.converged, "{convergence:?}");
+        assert!(convergence.iterations > 0 && convergence.residual <= tolerance);
+
+        let coupling = model.coupling(&space);
+        let velocity = space
+            .active_particles()
+            .map(|p| p.velocity)
+            .collect::<Vec<_>>();
+        for ((a, pairs), p) in coupling.iter().enumerate().zip(space.active_particles()) {
+            let sum = pairs.iter().fold(Vector::ZERO, |s, (b, k)| {
+                s + *k * (velocity[a] - velocity[*b])
+            });
+            let error = velocity[a] - dt * sum - initial[p.id];
+            assert!(error.length() <= 1e-3, "{error:?}");
+        }
+    }
+
+    // a step far past the explicit limit 0.125 h^2 / nu still damps the motion
+    // and keeps the momentum
+    #[test]
+    fn stable_beyond_explicit_limit() {
+        let dt = 0.01;
+        let viscosity = 1e4;
+        assert!(dt > 100. * 0.125 * 1.3 * 1.3 / viscosity);
+        let model = Implicit::<CubicSpline>::new(ImplicitSolver::default());
+        let mut space = viscous_cube(viscosity);
+        let momentum = |space: &Space| {
+            space
+                .particles()
+                .fold(Vector::ZERO, |s, p| s + p.velocity * p.mass)
+        };
+        let energy = |space: &Space| {
+            space
+                .particles()
+                .map(|p| p.mass * p.velocity.length_squared())
+                .sum::<Real>()
+        };
+        let (momentum_0, energy_0) = (momentum(&space), energy(&space));
+
+        let convergence = model.solve(&mut space, dt);
+        assert!(convergence.converged, "{convergence:?}");
+        assert!(energy(&space) < 0.5 * energy_0);
+        let drift = (momentum(&space) - momentum_0).length();
+        assert!(drift <= 1e-3 * energy_0.sqrt(), "{drift}");
+    }
+}
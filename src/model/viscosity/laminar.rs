@@ -0,0 +1,125 @@
+use std::marker::PhantomData;
+
+use crate::kernel;
+use crate::profiler;
+use crate::real::*;
+use crate::util_3d::*;
+
+use super::rheology::{self, Rheology};
+
+/// Physical viscosity (Morris et al. 1997),
+/// `sum m_b (mu_a + mu_b) r . grad W / (rho_a rho_b (r^2 + 0.01 h^2)) (v_a - v_b)`
+/// with the apparent viscosity `mu` of each particle.
+#[derive(Debug)]
+pub struct Laminar<T: kernel::Kernel> {
+    /// Indexed by `Particle::material`.
+    rheology: Vec<Rheology>,
+    _kernel: PhantomData<T>,
+}
+
+impl<T: kernel::Kernel + Sync + Send> Laminar<T> {
+    pub fn new(rheology: Rheology) -> Self {
+        Self::per_material(vec![rheology])
+    }
+
+    /// One rheology per material, the viscosity between two fluids is the mean of theirs.
+    pub fn per_material(rheology: Vec<Rheology>) -> Self {
+        assert!(rheology.iter().all(|r| r.max_viscosity() >= 0.));
+        Self {
+            rheology,
+            _kernel: PhantomData,
+        }
+    }
+
+    /// Upper bound over every shear rate and material.
+    pub fn kinematic_viscosity(&self, density: Real) -> Real {
+        let viscosity = self.rheology.iter().map(Rheology::max_viscosity);
+        viscosity.fold(0., Real::max) / density
+    }
+
+    /// Set the apparent viscosity of the active particles from their shear rate.
+    pub fn update_viscosity(&self, space: &mut Space) {
+        let _scope = profiler::scope("Laminar::update_viscosity");
+        let viscosity = space
+            .active_particles()
+            .map(|a| match self.rheology[a.material] {
+                Rheology::Newtonian { viscosity } => viscosity,
+                non_newtonian => {
+                    let gradient = rheology::velocity_gradient::<T>(space, a);
+                    non_newtonian.apparent_viscosity(rheology::shear_rate(gradient))
+                }
+            })
+            .collect::<Vec<_>>();
+        space
+            .active_particles_mut()
+            .zip(viscosity)
+            .for_each(|(p, mu)| p.viscosity = mu);
+    }
+
+    /// One entry per `space.active_particles()`.
+    pub fn accelration(&self, space: &Space) -> Vec<Vector> {
+        let _scope = profiler::scope("Laminar::accelration");
+        let radius = T::new(space.max_kernel_radius()).support_radius();
+        space
+            .active_particles()
+            .map(|a| {
+                let kernel = T::new(a.kernel_radius);
+                space
+                    .neighbour(a, radius)
+                    .filter(|b| b.id != a.id)
+                    .map(|b| {
+                        let r = a.position - b.position;
+                        let h = (a.kernel_radius + b.kernel_radius) / 2.;
+                        let gradient =
+                            (kernel.gradient(r) + T::new(b.kernel_radius).gradient(r)) / 2.;
+                        let f = r.dot(gradient) / (r.length_squared() + 0.01 * h * h);
+                        b.mass * (a.viscosity + b.viscosity) * f / (a.density * b.density)
+                            * (a.velocity - b.velocity)
+                    })
+                    .fold(Vector::ZERO, |a, b| a + b)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::CubicSpline;
+    use uom::si::dynamic_viscosity::poise;
+
+    // Poiseuille flow between walls at y = -w and y = w driven by a body force g along x,
+    // v_x = g (w^2 - y^2) / (2 nu). At steady state viscosity cancels g everywhere.
+    #[test]
+    fn poiseuille_profile() {
+        let h = 1.3;
+        let (nu, g, w) = (0.5, 2., 5.);
+        let viscosity = si::DynamicViscosity::new::<poise>(nu);
+        let model = Laminar::<CubicSpline>::new(Rheology::newtonian(viscosity));
+
+        let mut particles = init_setup::create_cube(1., 15, Vector::ZERO, 1., h);
+        particles.iter_mut().for_each(|p| {
+            p.density = 1.;
+            p.velocity = Vector::X * (g * (w * w - p.position.y.powi(2)) / (2. * nu));
+        });
+        let mut space = Space::new(h, particles);
+        model.update_viscosity(&mut space);
+
+        let acceleration = model.accelration(&space);
+        let inner = space
+            .active_particles()
+            .zip(acceleration)
+            .filter(|(p, _)| p.position.abs().max_element() < 7. - 2. * h);
+        let mut count = 0;
+        for (p, a) in inner {
+            assert!(
+                (a - Vector::X * -g).length() <= 0.05 * g,
+                "{a:?} at {:?}",
+                p.position
+            );
+            count += 1;
+        }
+        assert!(count > 0);
+        assert_eq!(model.kinematic_viscosity(2.), nu / 2.);
+    }
+}
@@ -1,5 +1,13 @@
 mod artificial;
+mod implicit;
+mod laminar;
+mod rheology;
+mod sps;
 // mod simple;
 
-pub use artificial::Artificial;
+pub use artificial::{AlphaSwitch, Artificial};
+pub use implicit::{Convergence, Implicit, ImplicitSolver};
+pub use laminar::Laminar;
+pub use rheology::Rheology;
+pub use sps::{Sps, Turbulence};
 // pub use simple::Simple;
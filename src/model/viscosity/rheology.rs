@@ -0,0 +1,213 @@
+use uom::si::dynamic_viscosity::poise;
+
+use crate::kernel::Kernel;
+use crate::real::*;
+use crate::util_3d::*;
+
+/// Apparent viscosity as a function of shear rate.
+/// Viscosities are in poise, stresses in dyn/cm^2 and times in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rheology {
+    Newtonian {
+        viscosity: Real,
+    },
+    /// `mu = k shear^(n - 1)`, shear thinning for `n < 1`, at most `max` near rest.
+    PowerLaw {
+        consistency: Real,
+        index: Real,
+        max: Real,
+    },
+    /// `mu = mu_inf + (mu_0 - mu_inf) / (1 + (lambda shear)^m)`.
+    Cross {
+        zero: Real,
+        infinite: Real,
+        time: Real,
+        index: Real,
+    },
+    /// `mu = mu_inf + (mu_0 - mu_inf) (1 + (lambda shear)^2)^((n - 1) / 2)`.
+    Carreau {
+        zero: Real,
+        infinite: Real,
+        time: Real,
+        index: Real,
+    },
+    /// Bingham plastic regularised as in Papanastasiou 1987,
+    /// `mu = mu_p + tau_y (1 - exp(-m shear)) / shear`.
+    Bingham {
+        yield_stress: Real,
+        plastic: Real,
+        regularisation: Real,
+    },
+}
+
+impl Rheology {
+    pub fn newtonian(viscosity: si::DynamicViscosity) -> Self {
+        Rheology::Newtonian {
+            viscosity: viscosity.get::<poise>(),
+        }
+    }
+
+    /// Example fluids for the viewer: `power_law`, `cross`, `carreau` and `bingham`
+    /// are paint or mud like, a few hundred times thicker than water at rest.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "power_law" => Some(Rheology::PowerLaw {
+                consistency: 10.,
+                index: 0.5,
+                max: 1e3,
+            }),
+            "cross" => Some(Rheology::Cross {
+                zero: 1e2,
+                infinite: 1.,
+                time: 1.,
+                index: 1.,
+            }),
+            "carreau" => Some(Rheology::Carreau {
+                zero: 1e2,
+                infinite: 1.,
+                time: 1.,
+                index: 0.4,
+            }),
+            "bingham" => Some(Rheology::Bingham {
+                yield_stress: 1e2,
+                plastic: 10.,
+                regularisation: 10.,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn apparent_viscosity(&self, shear_rate: Real) -> Real {
+        match *self {
+            Rheology::Newtonian { viscosity } => viscosity,
+            Rheology::PowerLaw {
+                consistency,
+                index,
+                max,
+            } => (consistency * shear_rate.powf(index - 1.)).min(max),
+            Rheology::Cross {
+                zero,
+                infinite,
+                time,
+                index,
+            } => infinite + (zero - infinite) / (1. + (time * shear_rate).powf(index)),
+            Rheology::Carreau {
+                zero,
+                infinite,
+                time,
+                index,
+            } => {
+                let thinning = (1. + (time * shear_rate).powi(2)).powf((index - 1.) / 2.);
+                infinite + (zero - infinite) * thinning
+            }
+            Rheology::Bingham {
+                yield_stress,
+                plastic,
+                regularisation,
+            } => {
+                let m = regularisation;
+                // (1 - exp(-m shear)) / shear tends to m at rest
+                let yielding = match m * shear_rate {
+                    x if x < 1e-6 => m,
+                    x => -(-x).exp_m1() / shear_rate,
+                };
+                plastic + yield_stress * yielding
+            }
+        }
+    }
+
+    /// Largest apparent viscosity at any shear rate, bounds the explicit time step.
+    pub fn max_viscosity(&self) -> Real {
+        match *self {
+            Rheology::Newtonian { viscosity } => viscosity,
+            Rheology::PowerLaw { max, .. } => max,
+            Rheology::Cross { zero, infinite, .. } | Rheology::Carreau { zero, infinite, .. } => {
+                zero.max(infinite)
+            }
+            Rheology::Bingham { .. } => self.apparent_viscosity(0.),
+        }
+    }
+}
+
+/// `grad v` at particle `a`, `sum m_b / rho_b (v_b - v_a) (x) grad W_ab`, entry `(i, j)` is `d v_i / d x_j`.
+pub fn velocity_gradient<T: Kernel>(space: &Space, a: &Particle) -> Matrix3 {
+    let kernel = T::new(a.kernel_radius);
+    space
+        .neighbour(a, kernel.support_radius())
+        .map(|b| {
+            let dv = (b.velocity - a.velocity) * (b.mass / b.density);
+            let w = kernel.gradient(a.position - b.position);
+            Matrix3::from_cols(dv * w.x, dv * w.y, dv * w.z)
+        })
+        .fold(Matrix3::ZERO, |s, m| s + m)
+}
+
+/// `div v`, the trace of `grad v`.
+pub fn divergence(gradient: Matrix3) -> Real {
+    gradient.x_axis.x + gradient.y_axis.y + gradient.z_axis.z
+}
+
+/// `curl v` from `grad v`.
+pub fn curl(gradient: Matrix3) -> Vector {
+    vector(
+        gradient.y_axis.z - gradient.z_axis.y,
+        gradient.z_axis.x - gradient.x_axis.z,
+        gradient.x_axis.y - gradient.y_axis.x,
+    )
+}
+
+/// `sqrt(2 D : D)` with the strain rate `D = (grad v + grad v^T) / 2`.
+pub fn shear_rate(gradient: Matrix3) -> Real {
+    let strain = (gradient + gradient.transpose()) * 0.5;
+    let squared = strain.x_axis.length_squared()
+        + strain.y_axis.length_squared()
+        + strain.z_axis.length_squared();
+    (2. * squared).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::CubicSpline;
+
+    #[test]
+    fn viscosity_curves() {
+        for name in ["power_law", "cross", "carreau", "bingham"] {
+            let rheology = Rheology::from_name(name).unwrap();
+            let low = rheology.apparent_viscosity(0.);
+            let high = rheology.apparent_viscosity(1e4);
+            assert!(high < low, "{name} should thin with shear");
+            assert_eq!(low, rheology.max_viscosity());
+        }
+
+        let power = Rheology::from_name("power_law").unwrap();
+        assert!((power.apparent_viscosity(100.) - 1.).abs() < 1e-5);
+
+        // past yield, stress is tau_y + mu_p shear
+        let bingham = Rheology::from_name("bingham").unwrap();
+        let stress = bingham.apparent_viscosity(100.) * 100.;
+        assert!((stress - (1e2 + 10. * 100.)).abs() < 1e-3 * stress);
+    }
+
+    // simple shear v_x = rate y
+    #[test]
+    fn shear_rate_of_simple_shear() {
+        let h = 1.3;
+        let rate = 3.;
+        let mut particles = init_setup::create_cube(1., 9, Vector::ZERO, 1., h);
+        particles.iter_mut().for_each(|p| {
+            p.density = 1.;
+            p.velocity = Vector::X * rate * p.position.y;
+        });
+        let space = Space::new(h, particles);
+
+        for p in space.particles() {
+            if p.position.abs().max_element() < 4. - 2. * h {
+                let gradient = velocity_gradient::<CubicSpline>(&space, p);
+                let shear = shear_rate(gradient);
+                assert!((shear - rate).abs() <= 0.05 * rate, "{shear} != {rate}");
+                assert!((gradient.y_axis.x - rate).abs() <= 0.05 * rate);
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::marker::PhantomData;
+
+use crate::kernel;
+use crate::profiler;
+use crate::real::*;
+use crate::util_3d::*;
+
+use super::rheology;
+
+/// Constants of the sub-particle-scale closure, the defaults are the ones of Dalrymple and
+/// Rogers 2006.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Turbulence {
+    /// Smagorinsky constant `C_s`, the eddy viscosity is `(C_s dx)^2 |S|`.
+    pub smagorinsky: Real,
+    /// Blin constant `C_I` of the isotropic part `2/3 C_I dx^2 |S|^2`.
+    pub blin: Real,
+}
+
+impl Default for Turbulence {
+    fn default() -> Self {
+        Self {
+            smagorinsky: 0.12,
+            blin: 0.0066,
+        }
+    }
+}
+
+/// Sub-particle-scale turbulence (Gotoh et al. 2001, Dalrymple and Rogers 2006), a large eddy
+/// simulation closure with the stress
+/// `tau / rho = 2 nu_t (S - tr S / 3) - 2/3 C_I dx^2 |S|^2 I` and `nu_t = (C_s dx)^2 |S|`,
+/// where `dx` is the particle spacing. Adds to the laminar viscosity, not instead of it.
+#[derive(Debug)]
+pub struct Sps<T: kernel::Kernel> {
+    turbulence: Turbulence,
+    spacing: Real,
+    _kernel: PhantomData<T>,
+}
+
+impl<T: kernel::Kernel + Sync + Send> Sps<T> {
+    pub fn new(turbulence: Turbulence, spacing: Real) -> Self {
+        assert!(turbulence.smagorinsky >= 0. && turbulence.blin >= 0.);
+        assert!(spacing > 0.);
+        Self {
+            turbulence,
+            spacing,
+            _kernel: PhantomData,
+        }
+    }
+
+    /// Kinematic eddy viscosity for the velocity gradient `grad v`.
+    pub fn eddy_viscosity(&self, gradient: Matrix3) -> Real {
+        (self.turbulence.smagorinsky * self.spacing).powi(2) * rheology::shear_rate(gradient)
+    }
+
+    // tau / rho
+    fn stress(&self, gradient: Matrix3) -> Matrix3 {
+        let shear = rheology::shear_rate(gradient);
+        let strain = (gradient + gradient.transpose()) * 0.5;
+        let deviatoric = strain - Matrix3::IDENTITY * (rheology::divergence(gradient) / 3.);
+        let isotropic = 2. / 3. * self.turbulence.blin * (self.spacing * shear).powi(2);
+        deviatoric * (2. * self.eddy_viscosity(gradient)) - Matrix3::IDENTITY * isotropic
+    }
+
+    /// `sum m_b (tau_a / rho_a^2 + tau_b / rho_b^2) grad W_ab`, one entry per
+    /// `space.active_particles()`, and the largest eddy viscosity among them.
+    pub fn accelration(&self, space: &Space) -> (Vec<Vector>, Real) {
+        let _scope = profiler::scope("Sps::accelration");
+        // neighbours may be inactive, so every particle needs its stress
+        let gradient = space.collect_by_id(|a| rheology::velocity_gradient::<T>(space, a));
+        let stress = space.collect_by_id(|a| self.stress(gradient[a.id]) * (1. / a.density));
+        let max_eddy_viscosity = space
+            .active_particles()
+            .map(|a| self.eddy_viscosity(gradient[a.id]))
+            .fold(0., Real::max);
+
+        let radius = T::new(space.max_kernel_radius()).support_radius();
+        let acceleration = space
+            .active_particles()
+            .map(|a| {
+                let kernel = T::new(a.kernel_radius);
+                space
+                    .neighbour(a, radius)
+                    .filter(|b| b.id != a.id)
+                    .map(|b| {
+                        let r = a.position - b.position;
+                        let gradient =
+                            (kernel.gradient(r) + T::new(b.kernel_radius).gradient(r)) / 2.;
+                        (stress[a.id] + stress[b.id]) * gradient * b.mass
+                    })
+                    .fold(Vector::ZERO, |a, b| a + b)
+            })
+            .collect();
+        (acceleration, max_eddy_viscosity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::CubicSpline;
+    use macroquad::rand::{gen_range, srand};
+
+    // simple shear v_x = rate y has a uniform stress, so no force away from the surface
+    #[test]
+    fn eddy_viscosity_of_shear() {
+        let h = 1.3;
+        let rate = 3.;
+        let model = Sps::<CubicSpline>::new(Turbulence::default(), 1.);
+        let mut particles = init_setup::create_cube(1., 15, Vector::ZERO, 1., h);
+        particles.iter_mut().for_each(|p| {
+            p.density = 1.;
+            p.velocity = Vector::X * rate * p.position.y;
+        });
+        let space = Space::new(h, particles);
+
+        let expected = (0.12 as Real).powi(2) * rate;
+        let (acceleration, max) = model.accelration(&space);
+        assert!(max >= 0.95 * expected);
+        let inner = space
+            .active_particles()
+            .zip(acceleration)
+            .filter(|(p, _)| p.position.abs().max_element() < 7. - 4. * h);
+        let mut count = 0;
+        for (p, a) in inner {
+            let gradient = rheology::velocity_gradient::<CubicSpline>(&space, p);
+            let nu = model.eddy_viscosity(gradient);
+            assert!(
+                (nu - expected).abs() <= 0.05 * expected,
+                "{nu} != {expected}"
+            );
+            assert!(a.length() <= 1e-3 * expected * rate, "{a:?}");
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn momentum_conservation() {
+        let h = 1.3;
+        let model = Sps::<CubicSpline>::new(Turbulence::default(), 1.);
+        srand(7);
+        let mut particles = init_setup::create_cube(1., 6, Vector::ZERO, 1., h);
+        particles.iter_mut().for_each(|p| {
+            p.density = gen_range(0.9, 1.1);
+            p.velocity = vector(gen_range(-1., 1.), gen_range(-1., 1.), gen_range(-1., 1.));
+        });
+        let space = Space::new(h, particles);
+
+        let (acceleration, max) = model.accelration(&space);
+        assert!(max > 0.);
+        let momentum = space
+            .active_particles()
+            .zip(&acceleration)
+            .fold(Vector::ZERO, |s, (p, a)| s + *a * p.mass);
+        let scale = acceleration.iter().map(|a| a.length()).sum::<Real>();
+        assert!(momentum.length() <= 1e-4 * scale, "{momentum:?}");
+    }
+}
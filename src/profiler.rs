@@ -0,0 +1,161 @@
+use std::{
+    cell::RefCell,
+    fs, io,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// A finished scope, `start` is relative to the first scope ever opened in the process.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub name: &'static str,
+    pub start: Duration,
+    pub duration: Duration,
+    pub depth: usize,
+    pub thread: usize,
+}
+
+#[derive(Debug, Default)]
+struct Recorder {
+    enabled: bool,
+    depth: usize,
+    thread: usize,
+    events: Vec<Event>,
+}
+
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+static THREAD_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static RECORDER: RefCell<Recorder> = RefCell::new(Recorder {
+        thread: THREAD_COUNT.fetch_add(1, Ordering::Relaxed),
+        ..Default::default()
+    });
+}
+
+/// Times the enclosing block until dropped, does nothing while recording is disabled.
+pub struct Scope {
+    name: &'static str,
+    start: Option<Instant>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let Some(start) = self.start else {
+            return;
+        };
+        let duration = start.elapsed();
+        let epoch = *EPOCH.get_or_init(|| start);
+        RECORDER.with_borrow_mut(|recorder| {
+            recorder.depth -= 1;
+            let event = Event {
+                name: self.name,
+                start: start.saturating_duration_since(epoch),
+                duration,
+                depth: recorder.depth,
+                thread: recorder.thread,
+            };
+            recorder.events.push(event);
+        });
+    }
+}
+
+pub fn scope(name: &'static str) -> Scope {
+    let start = RECORDER.with_borrow_mut(|recorder| {
+        if !recorder.enabled {
+            return None;
+        }
+        recorder.depth += 1;
+        let start = Instant::now();
+        EPOCH.get_or_init(|| start);
+        Some(start)
+    });
+    Scope { name, start }
+}
+
+/// Recording is per thread and starts disabled, return the previous state.
+pub fn set_enabled(enabled: bool) -> bool {
+    RECORDER.with_borrow_mut(|recorder| std::mem::replace(&mut recorder.enabled, enabled))
+}
+
+/// Take every event recorded on this thread so far, in the order the scopes closed.
+pub fn drain() -> Vec<Event> {
+    RECORDER.with_borrow_mut(|recorder| std::mem::take(&mut recorder.events))
+}
+
+// https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+#[derive(Serialize)]
+struct TraceEvent {
+    name: &'static str,
+    ph: &'static str,
+    ts: f64,
+    dur: f64,
+    pid: usize,
+    tid: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trace {
+    trace_events: Vec<TraceEvent>,
+    display_time_unit: &'static str,
+}
+
+/// Chrome trace-event JSON, open with `chrome://tracing` or https://ui.perfetto.dev
+pub fn chrome_trace(events: &[Event]) -> String {
+    let trace = Trace {
+        trace_events: events
+            .iter()
+            .map(|e| TraceEvent {
+                name: e.name,
+                ph: "X",
+                ts: e.start.as_secs_f64() * 1e6,
+                dur: e.duration.as_secs_f64() * 1e6,
+                pid: 1,
+                tid: e.thread,
+            })
+            .collect(),
+        display_time_unit: "ms",
+    };
+    serde_json::to_string(&trace).unwrap()
+}
+
+pub fn write_chrome_trace(path: impl AsRef<Path>, events: &[Event]) -> io::Result<()> {
+    fs::write(path, chrome_trace(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_scope() {
+        set_enabled(true);
+        {
+            let _outer = scope("outer");
+            let _inner = scope("inner");
+        }
+        set_enabled(false);
+        {
+            let _ignored = scope("ignored");
+        }
+
+        let events = drain();
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].name, events[0].depth), ("inner", 1));
+        assert_eq!((events[1].name, events[1].depth), ("outer", 0));
+        assert!(events[0].start >= events[1].start);
+        assert!(events[0].duration <= events[1].duration);
+        assert!(drain().is_empty());
+
+        let trace: serde_json::Value = serde_json::from_str(&chrome_trace(&events)).unwrap();
+        assert_eq!(trace["traceEvents"].as_array().unwrap().len(), 2);
+        assert_eq!(trace["traceEvents"][0]["ph"], "X");
+    }
+}
@@ -0,0 +1,49 @@
+//! Scalar and vector type of the solver, `f32` by default and `f64` with the `f64` feature.
+//! The viewer always draws in `f32`, use `to_f32` and `to_vec3` at that boundary.
+
+use macroquad::math::Vec3;
+
+#[cfg(not(feature = "f64"))]
+mod precision {
+    pub use macroquad::math::{
+        vec3 as vector, vec4 as vector4, Mat3 as Matrix3, Mat4 as Matrix4, Vec3 as Vector,
+        Vec4 as Vector4,
+    };
+    pub use std::f32::consts;
+    pub use uom::si::f32 as si;
+    pub use wide::f32x8 as RealLanes;
+
+    pub type Real = f32;
+
+    /// Number of `Real` in a `RealLanes`.
+    pub const LANES: usize = 8;
+}
+
+#[cfg(feature = "f64")]
+mod precision {
+    pub use macroquad::math::{
+        dvec3 as vector, dvec4 as vector4, DMat3 as Matrix3, DMat4 as Matrix4, DVec3 as Vector,
+        DVec4 as Vector4,
+    };
+    pub use std::f64::consts;
+    pub use uom::si::f64 as si;
+    pub use wide::f64x4 as RealLanes;
+
+    pub type Real = f64;
+
+    /// Number of `Real` in a `RealLanes`.
+    pub const LANES: usize = 4;
+}
+
+pub use precision::*;
+
+#[allow(clippy::unnecessary_cast)]
+#[inline]
+pub fn to_f32(value: Real) -> f32 {
+    value as f32
+}
+
+#[inline]
+pub fn to_vec3(value: Vector) -> Vec3 {
+    Vec3::new(to_f32(value.x), to_f32(value.y), to_f32(value.z))
+}
@@ -1,11 +1,70 @@
 use std::f32::consts::PI;
 
-use crate::Space;
+use crate::real::{to_f32, to_vec3, Real};
+use crate::simulator::StepTiming;
+use crate::util_3d::{Particle, Space};
 use macroquad::prelude::*;
 
+/// Field the particles are coloured by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    Distance,
+    Pressure,
+    /// Apparent viscosity of a physical viscosity model.
+    Viscosity,
+    /// Magnitude of `curl v`.
+    Vorticity,
+    /// Index of the fluid in the material table.
+    Material,
+    Temperature,
+}
+
+impl ColorMode {
+    pub const ALL: [ColorMode; 6] = [
+        ColorMode::Distance,
+        ColorMode::Pressure,
+        ColorMode::Viscosity,
+        ColorMode::Vorticity,
+        ColorMode::Material,
+        ColorMode::Temperature,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorMode::Distance => "distance",
+            ColorMode::Pressure => "pressure",
+            ColorMode::Viscosity => "viscosity",
+            ColorMode::Vorticity => "vorticity",
+            ColorMode::Material => "material",
+            ColorMode::Temperature => "temperature",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&m| m == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn value(&self, particle: &Particle) -> Real {
+        match self {
+            ColorMode::Distance => particle.position.length(),
+            ColorMode::Pressure => particle.pressure,
+            ColorMode::Viscosity => particle.viscosity,
+            ColorMode::Vorticity => particle.vorticity.length(),
+            ColorMode::Material => particle.material as Real,
+            ColorMode::Temperature => particle.temperature,
+        }
+    }
+}
+
 pub struct Render {
     current_angle: f32,
     angle_step: f32,
+    color_mode: ColorMode,
 }
 
 impl Render {
@@ -13,20 +72,57 @@ impl Render {
         Self {
             current_angle: 0.,
             angle_step: 2. * PI / 360. / 2.,
+            color_mode: ColorMode::Distance,
         }
     }
 
+    pub fn get_color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    pub fn set_color_mode(&mut self, mode: ColorMode) {
+        self.color_mode = mode;
+    }
+
+    pub fn next_color_mode(&mut self) {
+        self.color_mode = self.color_mode.next();
+    }
+
     fn draw_anchor(&self) {
         draw_line_3d(Vec3::ZERO, Vec3::X, RED);
         draw_line_3d(Vec3::ZERO, Vec3::Y, GREEN);
         draw_line_3d(Vec3::ZERO, Vec3::Z, BLUE);
     }
 
+    fn draw_timing(&self, timing: &StepTiming, hint: &[String]) {
+        set_default_camera();
+        let line_height = 18.;
+        let ms = |d: std::time::Duration| d.as_secs_f32() * 1e3;
+        // scopes close child first, sort by start to list them as a tree
+        let mut events = timing.events().to_vec();
+        events.sort_by_key(|e| e.start);
+        let lines = events
+            .iter()
+            .map(|e| {
+                let indent = 2 * e.depth;
+                let width = 32usize.saturating_sub(indent);
+                format!("{:indent$}{:<width$}{:>8.2} ms", "", e.name, ms(e.duration))
+            })
+            .chain(hint.iter().cloned());
+        lines.enumerate().for_each(|(i, line)| {
+            draw_text(&line, 10., (i + 1) as f32 * line_height, line_height, BLACK);
+        });
+    }
+
     pub async fn render_distance_from_zero(
         &mut self,
         space: &Space,
-        distance: f32,
+        solid: Option<&Space>,
+        distance: Real,
+        timing: &StepTiming,
+        hint: &[String],
     ) -> std::time::Instant {
+        let distance = to_f32(distance);
         clear_background(WHITE);
         // camera setting
         let pos = vec3(self.current_angle.cos(), self.current_angle.sin(), 0.5);
@@ -47,12 +143,35 @@ impl Render {
             }
         };
         self.draw_anchor();
+        // distance is scaled by the display distance, other fields by their range
+        let (low, high) = match self.color_mode {
+            ColorMode::Distance => (0., distance),
+            mode => space
+                .particles()
+                .map(|p| to_f32(mode.value(p)))
+                .fold((f32::INFINITY, -f32::INFINITY), |(l, h), v| {
+                    (l.min(v), h.max(v))
+                }),
+        };
         space.particles().for_each(|particle| {
-            let t = (particle.position.length() / distance).clamp(0., 1.);
+            let position = to_vec3(particle.position);
+            let value = to_f32(self.color_mode.value(particle));
+            let t = match high > low {
+                true => ((value - low) / (high - low)).clamp(0., 1.),
+                false => 0.,
+            };
             let color = lerp(LIME, YELLOW, ORANGE, t);
             // draw_sphere_wires(particle.position, spacing / 8., None, color);
-            draw_sphere(particle.position, particle.kernel_radius / 8., None, color);
+            draw_sphere(position, to_f32(particle.kernel_radius) / 8., None, color);
         });
+        solid
+            .into_iter()
+            .flat_map(|s| s.particles())
+            .for_each(|particle| {
+                let position = to_vec3(particle.position);
+                draw_sphere(position, to_f32(particle.kernel_radius) / 8., None, GRAY);
+            });
+        self.draw_timing(timing, hint);
 
         next_frame().await;
         self.current_angle += self.angle_step;
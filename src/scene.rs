@@ -0,0 +1,347 @@
+use crate::integrator::Integrator;
+use crate::model::density::{DensityMethod, Diffusion, SmoothingLength};
+use crate::model::material::Material;
+use crate::model::surface_tension::SurfaceTensionMethod;
+use crate::model::viscosity::{AlphaSwitch, ImplicitSolver, Rheology, Turbulence};
+use crate::real::*;
+use uom::si::dynamic_viscosity;
+
+/// How the fluids are arranged in the starting cube.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Water floating free without gravity.
+    #[default]
+    Cube,
+    /// A block of oil near the bottom of water, in a container under gravity.
+    /// It rises and spreads over the water.
+    OilOnWater,
+    /// A fluid twice as dense as water over water, in a container under gravity.
+    /// The interface starts slightly bent so that the Rayleigh-Taylor instability grows.
+    RayleighTaylor,
+    /// Hot water over a slab of ice, in a container under gravity. Needs conduction to melt.
+    HotWaterOnIce,
+}
+
+impl Layout {
+    /// `cube`, `oil_on_water`, `rayleigh_taylor` or `hot_water_on_ice`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "cube" => Some(Layout::Cube),
+            "oil_on_water" => Some(Layout::OilOnWater),
+            "rayleigh_taylor" => Some(Layout::RayleighTaylor),
+            "hot_water_on_ice" => Some(Layout::HotWaterOnIce),
+            _ => None,
+        }
+    }
+
+    /// Indexed by `Particle::material`.
+    pub fn materials(&self) -> Vec<Material> {
+        match self {
+            Layout::Cube | Layout::HotWaterOnIce => vec![Material::water()],
+            Layout::OilOnWater => vec![Material::water(), Material::oil()],
+            Layout::RayleighTaylor => vec![Material::water(), Material::heavy()],
+        }
+    }
+
+    /// Whether the fluids stand in a container under gravity.
+    pub fn contained(&self) -> bool {
+        *self != Layout::Cube
+    }
+
+    /// Material at `position` from the centre of a cube of side `2 half`.
+    pub fn material(&self, position: Vector, half: Real) -> usize {
+        match self {
+            Layout::Cube | Layout::HotWaterOnIce => 0,
+            Layout::OilOnWater => {
+                // next to the floor the solid adds no pressure from below, so the oil starts
+                // a few layers above it
+                let inside = position.x.abs().max(position.y.abs()) < half / 2.;
+                let low = -0.6 * half < position.z && position.z < 0.;
+                (inside && low) as usize
+            }
+            Layout::RayleighTaylor => {
+                let wave = |x: Real| (consts::PI * x / half).cos();
+                let interface = 0.1 * half * wave(position.x) * wave(position.y);
+                (position.z > interface) as usize
+            }
+        }
+    }
+
+    /// Starting temperature at `position` from the centre of a cube of side `2 half`, in
+    /// degrees Celsius, `None` where the scene decides.
+    pub fn temperature(&self, position: Vector, half: Real) -> Option<Real> {
+        match self {
+            Layout::HotWaterOnIce if position.z < -half / 3. => Some(-10.),
+            Layout::HotWaterOnIce => Some(80.),
+            _ => None,
+        }
+    }
+}
+
+/// How the models visit the neighbours of a particle.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PairLoop {
+    /// Search the hash grid of `Space` around every particle.
+    #[default]
+    Space,
+    /// Walk a `NeighbourList` over a `ParticleSoa` snapshot, both built for each force
+    /// evaluation.
+    Soa,
+    /// Same as `Soa`, `LANES` neighbours at a time.
+    Simd,
+}
+
+impl PairLoop {
+    pub const ALL: [PairLoop; 3] = [PairLoop::Space, PairLoop::Soa, PairLoop::Simd];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PairLoop::Space => "space",
+            PairLoop::Soa => "soa",
+            PairLoop::Simd => "simd",
+        }
+    }
+
+    /// `space`, `soa` or `simd`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+}
+
+/// Everything a run can choose before `Simulator::new`.
+#[derive(Debug, Clone)]
+pub struct Scene {
+    /// The fluid starts as a cube of `particle_per_side^3` particles.
+    pub particle_per_side: isize,
+    /// Fluids in the cube, the ones with several want `DensityMethod::NumberDensity`.
+    pub layout: Layout,
+    pub integrator: Integrator,
+    pub density: DensityMethod,
+    pub smoothing_length: SmoothingLength,
+    /// delta-SPH density diffusion, needs `DensityMethod::Continuity`.
+    pub density_diffusion: Option<Diffusion>,
+    /// Per-particle artificial viscosity, constant `alpha` by default.
+    pub alpha_switch: AlphaSwitch,
+    /// Scale artificial viscosity down in shear flow (Balsara 1995).
+    pub balsara: bool,
+    /// Physical viscosity (Morris et al. 1997) added to the artificial one.
+    pub viscosity: Option<Rheology>,
+    /// Solve `viscosity` implicitly after each step instead of adding it to the forces,
+    /// so very viscous fluids do not limit the time step.
+    pub implicit_viscosity: Option<ImplicitSolver>,
+    /// Sub-particle-scale eddy viscosity on top of the laminar one, always explicit.
+    pub turbulence: Option<Turbulence>,
+    /// Vorticity confinement epsilon in cm/s, spins up vortices the viscosity damped.
+    pub vorticity_confinement: Option<Real>,
+    pub surface_tension: SurfaceTensionMethod,
+    /// Solid plate under the fluid, wide enough for it to spread on.
+    pub plate: bool,
+    /// Static contact angle of the fluid on the plate in degrees, held by the csf surface
+    /// tension.
+    pub contact_angle: Option<Real>,
+    /// Starting temperature of the fluid in degrees Celsius.
+    pub temperature: Real,
+    /// The plate or container is held at this temperature in degrees Celsius, needs
+    /// `conduction`.
+    pub wall_temperature: Option<Real>,
+    /// Heat conduction with the conductivity of every material times this factor.
+    /// Water is so poor a conductor that at 1 heat barely moves within a run.
+    pub conduction: Option<Real>,
+    /// XSPH epsilon, particles move with their velocity blended with the neighbour average.
+    pub xsph: Option<Real>,
+    /// Every step this long in seconds instead of the adaptive one, for runs that must repeat
+    /// exactly.
+    pub time_step: Option<Real>,
+    /// `Some(n)` gives every particle its own power-of-two step, at most `n` levels below the
+    /// largest one. Block steps are always kick-drift-kick.
+    pub block_levels: Option<u32>,
+    /// Only summation density and the pressure and artificial viscosity forces follow it,
+    /// every other model searches `Space`.
+    pub pair_loop: PairLoop,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            particle_per_side: 15,
+            layout: Layout::default(),
+            integrator: Integrator::default(),
+            density: DensityMethod::default(),
+            smoothing_length: SmoothingLength::default(),
+            density_diffusion: None,
+            alpha_switch: AlphaSwitch::default(),
+            balsara: false,
+            viscosity: None,
+            implicit_viscosity: None,
+            turbulence: None,
+            vorticity_confinement: None,
+            surface_tension: SurfaceTensionMethod::default(),
+            plate: false,
+            contact_angle: None,
+            temperature: 20.,
+            wall_temperature: None,
+            conduction: None,
+            xsph: None,
+            time_step: None,
+            block_levels: None,
+            pair_loop: PairLoop::default(),
+        }
+    }
+}
+
+impl Scene {
+    /// Scene flags shared by the viewer and the benchmark, any other argument is left to the
+    /// caller.
+    pub fn from_args(args: &[String]) -> Self {
+        let value_of = |flag: &str| {
+            args.iter()
+                .position(|a| a == flag)
+                .and_then(|i| args.get(i + 1).cloned())
+        };
+
+        let mut scene = Scene::default();
+        // `--integrator <name>` one of symplectic_euler, velocity_verlet, predictor_corrector,
+        // rk4
+        if let Some(name) = value_of("--integrator") {
+            scene.integrator =
+                Integrator::from_name(&name).unwrap_or_else(|| panic!("unknown integrator {name}"));
+        }
+        // `--layout <name>` cube, or oil_on_water, rayleigh_taylor and hot_water_on_ice in a
+        // container with number density, the ice melting with conduction sped up ten thousand
+        // times
+        if let Some(name) = value_of("--layout") {
+            scene.layout =
+                Layout::from_name(&name).unwrap_or_else(|| panic!("unknown layout {name}"));
+            if scene.layout.contained() {
+                scene.density = DensityMethod::NumberDensity;
+            }
+            if scene.layout == Layout::HotWaterOnIce {
+                scene.conduction = Some(1e4);
+            }
+        }
+        // `--density <name>` summation, number_density, grad_h, continuity, or continuity
+        // re-initialised with shepard or mls
+        if let Some(name) = value_of("--density") {
+            scene.density = DensityMethod::from_name(&name, 30)
+                .unwrap_or_else(|| panic!("unknown density method {name}"));
+        }
+        // `--delta <coefficient>` delta-SPH density diffusion, needs continuity density
+        scene.density_diffusion = value_of("--delta").map(|delta| Diffusion {
+            delta: delta.parse().expect("--delta"),
+            ..Default::default()
+        });
+        // `--smoothing-length <rule>` fixed=<h>, adaptive=<eta>[,<offset>] or neighbours=<count>
+        if let Some(rule) = value_of("--smoothing-length") {
+            scene.smoothing_length = SmoothingLength::from_name(&rule)
+                .unwrap_or_else(|| panic!("unknown smoothing length {rule}"));
+        }
+        // `--h-range <min>,<max>` limit every smoothing length to [min, max]
+        if let Some(range) = value_of("--h-range") {
+            let (min, max) = range.split_once(',').expect("--h-range <min>,<max>");
+            scene.smoothing_length = scene.smoothing_length.clamped(
+                min.parse().expect("--h-range"),
+                max.parse().expect("--h-range"),
+            );
+        }
+        // `--alpha-switch <name>` constant, morris_monaghan or cullen_dehnen artificial viscosity
+        if let Some(name) = value_of("--alpha-switch") {
+            scene.alpha_switch = AlphaSwitch::from_name(&name)
+                .unwrap_or_else(|| panic!("unknown alpha switch {name}"));
+        }
+        // `--balsara` limit artificial viscosity in shear flow
+        scene.balsara = args.iter().any(|a| a == "--balsara");
+        // `--viscosity <centipoise>` physical viscosity on top of the artificial one, water is 1
+        scene.viscosity = value_of("--viscosity").map(|v| {
+            let viscosity = v.parse().expect("--viscosity");
+            Rheology::newtonian(si::DynamicViscosity::new::<dynamic_viscosity::centipoise>(
+                viscosity,
+            ))
+        });
+        // `--rheology <name>` shear thinning example fluid, power_law, cross, carreau or bingham
+        if let Some(name) = value_of("--rheology") {
+            let rheology =
+                Rheology::from_name(&name).unwrap_or_else(|| panic!("unknown rheology {name}"));
+            scene.viscosity = Some(rheology);
+        }
+        // `--implicit-viscosity <tolerance>,<max iterations>` solve the physical viscosity implicitly
+        scene.implicit_viscosity = value_of("--implicit-viscosity").map(|settings| {
+            let (tolerance, max_iterations) = settings
+                .split_once(',')
+                .expect("--implicit-viscosity <tolerance>,<max iterations>");
+            ImplicitSolver {
+                tolerance: tolerance.parse().expect("--implicit-viscosity"),
+                max_iterations: max_iterations.parse().expect("--implicit-viscosity"),
+            }
+        });
+        // `--turbulence <smagorinsky>,<blin>` sub-particle-scale eddy viscosity, usually 0.12,0.0066
+        scene.turbulence = value_of("--turbulence").map(|constants| {
+            let (smagorinsky, blin) = constants
+                .split_once(',')
+                .expect("--turbulence <smagorinsky>,<blin>");
+            Turbulence {
+                smagorinsky: smagorinsky.parse().expect("--turbulence"),
+                blin: blin.parse().expect("--turbulence"),
+            }
+        });
+        // `--vorticity-confinement <epsilon>` spin small vortices back up, in cm/s
+        scene.vorticity_confinement = value_of("--vorticity-confinement")
+            .map(|epsilon| epsilon.parse().expect("--vorticity-confinement"));
+        // `--surface-tension <name>` becker_teschner_07, akinci13 or csf
+        if let Some(name) = value_of("--surface-tension") {
+            scene.surface_tension = SurfaceTensionMethod::from_name(&name)
+                .unwrap_or_else(|| panic!("unknown surface tension {name}"));
+        }
+        // `--adhesion <beta>` pull of the solid on the fluid, needs akinci13
+        if let Some(beta) = value_of("--adhesion") {
+            let SurfaceTensionMethod::Akinci13 { adhesion, .. } = &mut scene.surface_tension else {
+                panic!("--adhesion needs --surface-tension akinci13");
+            };
+            *adhesion = beta.parse().expect("--adhesion");
+        }
+        // `--surface-threshold <value>` smallest `h |grad c|` that counts as surface, needs csf
+        if let Some(value) = value_of("--surface-threshold") {
+            let SurfaceTensionMethod::Csf { threshold, .. } = &mut scene.surface_tension else {
+                panic!("--surface-threshold needs --surface-tension csf");
+            };
+            *threshold = value.parse().expect("--surface-threshold");
+        }
+        // `--plate` solid plate under the fluid
+        scene.plate = args.iter().any(|a| a == "--plate");
+        // `--contact-angle <degrees>` of the fluid on the plate, needs csf
+        scene.contact_angle = value_of("--contact-angle").map(|angle| {
+            assert!(
+                scene.plate && matches!(scene.surface_tension, SurfaceTensionMethod::Csf { .. }),
+                "--contact-angle needs --plate and --surface-tension csf"
+            );
+            angle.parse().expect("--contact-angle")
+        });
+        // `--temperature <celsius>` of the fluid at the start
+        if let Some(temperature) = value_of("--temperature") {
+            scene.temperature = temperature.parse().expect("--temperature");
+        }
+        // `--conduction <factor>` heat conduction with every conductivity scaled by factor
+        if let Some(factor) = value_of("--conduction") {
+            scene.conduction = Some(factor.parse().expect("--conduction"));
+        }
+        // `--wall-temperature <celsius>` hold the plate or container there, needs conduction
+        scene.wall_temperature = value_of("--wall-temperature").map(|temperature| {
+            assert!(
+                scene.conduction.is_some() && (scene.plate || scene.layout.contained()),
+                "--wall-temperature needs --conduction and a plate or container"
+            );
+            temperature.parse().expect("--wall-temperature")
+        });
+        // `--xsph <epsilon>` move particles with a velocity blended with their neighbours'
+        scene.xsph = value_of("--xsph").map(|epsilon| epsilon.parse().expect("--xsph"));
+        // `--dt <seconds>` fixed time step instead of the adaptive one
+        scene.time_step = value_of("--dt").map(|dt| dt.parse().expect("--dt"));
+        // `--block-levels <n>` individual power-of-two time steps, at most n levels deep
+        scene.block_levels = value_of("--block-levels").map(|n| n.parse().expect("--block-levels"));
+        // `--pair-loop <name>` space, or soa and simd to walk neighbour lists over a snapshot
+        if let Some(name) = value_of("--pair-loop") {
+            scene.pair_loop =
+                PairLoop::from_name(&name).unwrap_or_else(|| panic!("unknown pair loop {name}"));
+        }
+        scene
+    }
+}
@@ -1,115 +1,661 @@
+use crate::integrator::{Integrator, System};
 use crate::kernel::*;
+use crate::model::material::Material;
 use crate::model::*;
+use crate::profiler::{self, Event};
+use crate::real::*;
+use crate::scene::{PairLoop, Scene};
+use crate::time_step::{self, TimeStep};
 use crate::util_3d::*;
-use itertools::{izip, Itertools};
-use macroquad::prelude::*;
-use rayon::prelude::*;
-use uom::si::{
-    acceleration,
-    f32::{Acceleration, MassDensity},
-    mass_density,
-};
-
-struct Material {
-    density: MassDensity,
+use itertools::izip;
+use std::time::Duration;
+use uom::si::acceleration;
+
+use si::Acceleration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Neighbour lists of the `soa` and `simd` pair loops, the `space` one searches inside
+    /// every model instead.
+    NeighbourSearch,
+    Density,
+    Pressure,
+    Viscosity,
+    SurfaceTension,
+    Heat,
+    TimeStep,
+    Integration,
+    SpaceUpdate,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 9] = [
+        Phase::NeighbourSearch,
+        Phase::Density,
+        Phase::Pressure,
+        Phase::Viscosity,
+        Phase::SurfaceTension,
+        Phase::Heat,
+        Phase::TimeStep,
+        Phase::Integration,
+        Phase::SpaceUpdate,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::NeighbourSearch => "neighbour_search",
+            Phase::Density => "density",
+            Phase::Pressure => "pressure",
+            Phase::Viscosity => "viscosity",
+            Phase::SurfaceTension => "surface_tension",
+            Phase::Heat => "heat",
+            Phase::TimeStep => "time_step",
+            Phase::Integration => "integration",
+            Phase::SpaceUpdate => "space_update",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BlockState {
+    /// Level of every particle by id, see `time_step::Block`.
+    level: Vec<u32>,
+    /// Position in the current block in units of the smallest step.
+    tick: u64,
+    /// Step of level 0, taken again from the stable steps at the start of every block.
+    max_step: Real,
+    active_count: usize,
+    /// Particles of the last kick whose stable step is shorter than the finest level.
+    unstable_count: usize,
 }
 
-impl Material {
-    fn get_density(&self) -> f32 {
-        self.density
-            .get::<mass_density::gram_per_cubic_centimeter>()
+/// Wall time spent in a single `Simulator::update`, collected from `profiler` scopes.
+#[derive(Debug, Default, Clone)]
+pub struct StepTiming {
+    events: Vec<Event>,
+}
+
+impl StepTiming {
+    pub fn get(&self, phase: Phase) -> Duration {
+        self.get_by_name(phase.name())
+    }
+
+    /// Sum of every scope with this name, including the ones opened inside the models.
+    pub fn get_by_name(&self, name: &str) -> Duration {
+        self.events
+            .iter()
+            .filter(|e| e.name == name)
+            .map(|e| e.duration)
+            .sum()
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
     }
 }
 
 #[derive(Debug)]
 pub struct Simulator {
-    t: f32,
-    time_step: f32,
+    t: Real,
+    time_step: Real,
+    time_stepping: TimeStep,
+    integrator: Integrator,
+    /// Acceleration at the current state by particle id, left by integrators that already evaluated it.
+    cached_acceleration: Option<Vec<Vector>>,
+    block_state: Option<BlockState>,
+    speed_of_sound: Real,
     space: Space,
+    smoothing_length: density::SmoothingLength,
     density_model: density::Density<CubicSpline>,
+    /// Set when density and kernel radius are solved together instead of with `density_model`.
+    grad_h_model: Option<density::GradH<CubicSpline>>,
+    /// Set when density follows the continuity equation instead of `density_model`.
+    continuity_model: Option<density::Continuity<CubicSpline>>,
+    delta_sph: Option<density::DeltaSph<CubicSpline>>,
+    /// `d density / dt` by particle id, only used with `continuity_model`.
+    density_rate: Vec<Real>,
+    step_count: usize,
     pressure_model: pressure::Tait<CubicSpline>,
     viscosity_model: viscosity::Artificial<CubicSpline>,
-    surface_tension_model: surface_tension::BeakerTeschner07<CubicSpline>,
-    display_distance: f32,
+    laminar_model: Option<viscosity::Laminar<CubicSpline>>,
+    /// Set when `laminar_model` is solved implicitly instead of explicitly.
+    implicit_viscosity: Option<viscosity::Implicit<CubicSpline>>,
+    viscosity_convergence: Option<viscosity::Convergence>,
+    turbulence_model: Option<viscosity::Sps<CubicSpline>>,
+    /// Largest eddy viscosity seen by the last force evaluation.
+    eddy_viscosity: Real,
+    /// Keeps the vorticity of every particle up to date, confinement is optional.
+    vorticity_model: vorticity::Vorticity<CubicSpline>,
+    xsph: Option<xsph::Xsph<CubicSpline>>,
+    /// Indexed by `Particle::material`.
+    materials: Vec<Material>,
+    /// Zero unless the fluids stand in a container.
+    gravity: Vector,
+    surface_tension_model: surface_tension::BeckerTeschner07<CubicSpline>,
+    /// Used instead of `surface_tension_model` when set.
+    akinci_model: Option<surface_tension::Akinci13<CubicSpline>>,
+    /// Used instead of `surface_tension_model` when set.
+    csf_model: Option<surface_tension::Csf<CubicSpline>>,
+    boundary: Option<boundary::Boundary<CubicSpline>>,
+    conduction: Option<heat::Conduction<CubicSpline>>,
+    /// Set with `conduction` when a material can freeze.
+    phase_change: Option<heat::PhaseChange>,
+    display_distance: Real,
+    pair_loop: PairLoop,
+    step_timing: StepTiming,
+    trace: Option<Vec<Event>>,
 }
 
 impl Simulator {
-    pub fn setup() -> Self {
-        // Constant for water
-        let water = Material {
-            density: MassDensity::new::<mass_density::kilogram_per_cubic_meter>(1000.),
-        };
+    pub fn new(scene: Scene) -> Self {
+        let particle_per_side = scene.particle_per_side;
 
-        let rest_density = water.get_density();
+        let materials = scene.layout.materials();
+        let rest_density = materials[0].rest_density();
         let gravity: Acceleration = Acceleration::new::<acceleration::standard_gravity>(1.);
         let gravity = gravity.get::<acceleration::centimeter_per_second_squared>();
 
         let mass = 1.; // gram
-        let particle_per_side = 15isize;
 
         let particle_count = particle_per_side.pow(3);
-        let total_mass = mass * particle_count as f32;
-        let spacing = (total_mass / rest_density).powf(1. / 3.) / particle_per_side as f32;
+        let total_mass = mass * particle_count as Real;
+        let spacing = (total_mass / rest_density).powf(1. / 3.) / particle_per_side as Real;
 
-        let default_kernel_radius = 1.3 * (mass / rest_density).powf(1. / 3.);
+        let smoothing_length = scene.smoothing_length;
+        let default_kernel_radius =
+            smoothing_length.rest_kernel_radius::<CubicSpline>(mass, rest_density);
 
         dbg!(rest_density, total_mass, spacing, default_kernel_radius);
-        let particles = init_setup::create_cube(
+        let mut particles = init_setup::create_cube(
             spacing,
             particle_per_side,
-            Vec3::ZERO,
+            Vector::ZERO,
             mass,
             default_kernel_radius,
         );
+        // every material at its own rest density on the same lattice
+        let half = spacing * particle_per_side as Real / 2.;
+        particles.iter_mut().for_each(|p| {
+            p.material = scene.layout.material(p.position, half);
+            p.mass = materials[p.material].rest_density() * spacing.powi(3);
+            p.temperature = scene
+                .layout
+                .temperature(p.position, half)
+                .unwrap_or(scene.temperature);
+        });
 
         let speed_of_sound =
-            10. * f32::sqrt(2. * gravity * spacing * particle_per_side as f32 / 2.);
+            10. * Real::sqrt(2. * gravity * spacing * particle_per_side as Real / 2.);
         let alpha = 0.08;
 
         let space = Space::new(default_kernel_radius, particles);
         let time_step = 0.4 * default_kernel_radius / (speed_of_sound * (1. + 0.6 * alpha));
+        let adaptive = time_step::Adaptive {
+            min: 0.01 * time_step,
+            max: 10. * time_step,
+            ..Default::default()
+        };
+        assert!(
+            scene.time_step.is_none() || scene.block_levels.is_none(),
+            "a fixed time step leaves no room for block time steps"
+        );
+        let time_stepping = match (scene.time_step, scene.block_levels) {
+            (Some(dt), _) => TimeStep::Fixed(dt),
+            (None, Some(max_level)) => {
+                assert_eq!(
+                    scene.integrator,
+                    Integrator::VelocityVerlet,
+                    "block time steps only support kick-drift-kick"
+                );
+                TimeStep::Block(time_step::Block::new(adaptive, max_level))
+            }
+            (None, None) => TimeStep::Adaptive(adaptive),
+        };
+
+        let grad_h_model = match scene.density {
+            density::DensityMethod::GradH => Some(density::GradH::new(smoothing_length, 1e-4, 20)),
+            _ => None,
+        };
+        let continuity_model = match scene.density {
+            density::DensityMethod::Summation
+            | density::DensityMethod::NumberDensity
+            | density::DensityMethod::GradH => None,
+            density::DensityMethod::Continuity { reinit, interval } => {
+                Some(density::Continuity::new(reinit, interval, smoothing_length))
+            }
+        };
+
+        let delta_sph = scene.density_diffusion.map(|diffusion| {
+            assert!(
+                continuity_model.is_some(),
+                "density diffusion needs continuity density"
+            );
+            density::DeltaSph::new(
+                diffusion.delta,
+                speed_of_sound,
+                diffusion.gradient_correction,
+            )
+        });
+
+        // the viscosity of the scene stands for every material's
+        let laminar_model = match scene.viscosity {
+            Some(rheology) => Some(viscosity::Laminar::per_material(vec![
+                rheology;
+                materials.len()
+            ])),
+            None => materials.iter().any(|m| m.viscosity.is_some()).then(|| {
+                let inviscid = viscosity::Rheology::Newtonian { viscosity: 0. };
+                let rheology = materials.iter().map(|m| m.viscosity.unwrap_or(inviscid));
+                viscosity::Laminar::per_material(rheology.collect())
+            }),
+        };
+        let implicit_viscosity = scene.implicit_viscosity.map(|solver| {
+            assert!(
+                laminar_model.is_some(),
+                "implicit viscosity needs a physical viscosity"
+            );
+            assert!(
+                scene.block_levels.is_none(),
+                "implicit viscosity solves every particle at once"
+            );
+            viscosity::Implicit::new(solver)
+        });
+
+        let akinci_model = match scene.surface_tension {
+            surface_tension::SurfaceTensionMethod::Akinci13 { tension, adhesion } => Some(
+                surface_tension::Akinci13::new(tension, adhesion, rest_density),
+            ),
+            _ => None,
+        };
+        let csf_model = match scene.surface_tension {
+            // several fluids each pull with their own tension
+            surface_tension::SurfaceTensionMethod::Csf {
+                coefficient,
+                threshold,
+            } => Some(match materials.len() {
+                1 => surface_tension::Csf::new(coefficient, threshold),
+                _ => {
+                    let coefficient = materials.iter().map(|m| m.surface_tension).collect();
+                    surface_tension::Csf::per_material(coefficient, threshold)
+                }
+            }),
+            _ => None,
+        };
+        assert!(
+            !(scene.plate && scene.layout.contained()),
+            "the container already has a floor"
+        );
+        let boundary = (scene.plate || scene.layout.contained()).then(|| {
+            let half = spacing * (particle_per_side - 1) as Real / 2.;
+            let solid = match scene.layout.contained() {
+                true => init_setup::create_container(
+                    spacing,
+                    particle_per_side,
+                    Vector::ZERO,
+                    mass,
+                    default_kernel_radius,
+                ),
+                false => init_setup::create_plate(
+                    spacing,
+                    2 * particle_per_side,
+                    vector(0., 0., -half - spacing),
+                    mass,
+                    default_kernel_radius,
+                ),
+            };
+            // about the square of the largest fluid speed
+            let boundary = boundary::Boundary::new(solid, spacing, (speed_of_sound / 10.).powi(2));
+            let boundary = match scene.wall_temperature {
+                Some(temperature) => boundary.with_temperature(temperature),
+                None => boundary,
+            };
+            match scene.contact_angle {
+                Some(angle) => boundary.with_contact_angle(angle.to_radians()),
+                None => boundary,
+            }
+        });
+        let conduction = scene.conduction.map(|factor| {
+            heat::Conduction::new(
+                materials
+                    .iter()
+                    .map(|m| factor * m.thermal_conductivity())
+                    .collect(),
+                materials.iter().map(Material::specific_heat).collect(),
+            )
+        });
+        let freezes = materials.iter().any(|m| m.melting_point.is_some());
+        let phase_change = (conduction.is_some() && freezes).then(|| {
+            heat::PhaseChange::new(
+                materials
+                    .iter()
+                    .map(|m| m.melting_point.map(|t| (t, m.latent_heat_of_melting())))
+                    .collect(),
+                materials.iter().map(Material::specific_heat).collect(),
+            )
+        });
+
+        let number_density = scene.density == density::DensityMethod::NumberDensity;
+        let density_model = match number_density {
+            true => density::Density::new(smoothing_length).with_number_density(),
+            false => density::Density::new(smoothing_length),
+        };
+        // the soft equation of state of a free cube would sag under gravity
+        let pressure_model = match scene.layout.contained() {
+            true => pressure::Tait::from_materials(&materials, speed_of_sound).without_tension(),
+            false => pressure::Tait::new(rest_density, materials[0].gamma, speed_of_sound),
+        };
+        let pressure_model = match number_density {
+            true => pressure_model.with_number_density(),
+            false => pressure_model,
+        };
+        let gravity = match scene.layout.contained() {
+            true => Vector::NEG_Z * gravity,
+            false => Vector::ZERO,
+        };
 
         let mut obj = Self {
             t: 0.,
             time_step,
+            time_stepping,
+            integrator: scene.integrator,
+            cached_acceleration: None,
+            block_state: None,
+            speed_of_sound,
             space,
-            density_model: density::Density::new(),
-            pressure_model: pressure::Tait::new(rest_density, 7, speed_of_sound),
-            viscosity_model: viscosity::Artificial::new(alpha, speed_of_sound),
-            surface_tension_model: surface_tension::BeakerTeschner07::new(),
-            display_distance: particle_per_side as f32 * spacing,
+            smoothing_length,
+            density_model,
+            grad_h_model,
+            continuity_model,
+            delta_sph,
+            density_rate: vec![0.; particle_count as usize],
+            step_count: 0,
+            pressure_model,
+            viscosity_model: viscosity::Artificial::new(alpha, speed_of_sound)
+                .with_switch(scene.alpha_switch, scene.balsara),
+            laminar_model,
+            implicit_viscosity,
+            viscosity_convergence: None,
+            turbulence_model: scene
+                .turbulence
+                .map(|turbulence| viscosity::Sps::new(turbulence, spacing)),
+            eddy_viscosity: 0.,
+            vorticity_model: vorticity::Vorticity::new(scene.vorticity_confinement.unwrap_or(0.)),
+            xsph: scene.xsph.map(xsph::Xsph::new),
+            materials,
+            gravity,
+            surface_tension_model: surface_tension::BeckerTeschner07::new(),
+            akinci_model,
+            csf_model,
+            boundary,
+            conduction,
+            phase_change,
+            display_distance: particle_per_side as Real * spacing,
+            pair_loop: scene.pair_loop,
+            step_timing: StepTiming::default(),
+            trace: None,
         };
 
-        obj.density_model.update_density(&mut obj.space);
-        obj.density_model.update_density(&mut obj.space);
+        obj.viscosity_model.reset_alpha(&mut obj.space);
+        match &obj.grad_h_model {
+            Some(grad_h) => _ = grad_h.update_density(&mut obj.space),
+            None => {
+                obj.density_model.update_density(&mut obj.space);
+                obj.density_model.update_density(&mut obj.space);
+            }
+        }
+        if let Some(phase_change) = &obj.phase_change {
+            obj.space.particles_mut().for_each(|p| {
+                p.liquid_fraction = phase_change.liquid_fraction(p.material, p.temperature);
+            });
+        }
+        if obj.continuity_model.is_some() {
+            // start at rest, summation would leave the free surface underdense
+            let materials = &obj.materials;
+            obj.space
+                .particles_mut()
+                .for_each(|p| p.density = materials[p.material].rest_density());
+        }
         obj
     }
 
     pub fn update(&mut self) {
-        self.density_model.update_density(&mut self.space);
-        self.pressure_model.update_pressure(&mut self.space);
+        let enabled = profiler::set_enabled(true);
+        {
+            let _scope = profiler::scope("step");
+            self.step();
+        }
+        profiler::set_enabled(enabled);
 
-        let pressure_acc = self.pressure_model.accelration(&self.space);
-        let viscosity_acc = self.viscosity_model.accelration(&self.space);
-        let surface_tension_acc = self.surface_tension_model.accelration(&self.space);
+        let events = profiler::drain();
+        if let Some(trace) = &mut self.trace {
+            trace.extend_from_slice(&events);
+        }
+        self.step_timing = StepTiming { events };
+    }
 
-        let acceleration =
-            izip!(pressure_acc, viscosity_acc, surface_tension_acc).map(|t| t.0 + t.1 + t.2);
+    fn step(&mut self) {
+        if let Some(continuity) = &self.continuity_model {
+            if continuity.should_reinit(self.step_count) {
+                continuity.reinit_density(&mut self.space);
+                self.cached_acceleration = None;
+            }
+        }
+        self.step_count += 1;
 
-        self.space
-            .particles_mut()
-            .zip(acceleration)
-            .for_each(|(p, a)| {
-                p.velocity += a * self.time_step / 2.;
-                p.position += p.velocity * self.time_step;
-                p.velocity += a * self.time_step / 2.;
-            });
+        if let TimeStep::Block(block) = self.time_stepping.clone() {
+            self.block_step(&block);
+            return;
+        }
 
-        self.t += self.time_step;
-        self.space.update();
+        let acceleration = match self.cached_acceleration.take() {
+            Some(acceleration) => acceleration,
+            None => self.acceleration(),
+        };
+
+        self.time_step = {
+            let _scope = profiler::scope(Phase::TimeStep.name());
+            match &self.time_stepping {
+                TimeStep::Fixed(dt) => *dt,
+                TimeStep::Adaptive(policy) => policy.compute::<CubicSpline>(
+                    &self.space,
+                    &acceleration,
+                    self.speed_of_sound,
+                    |h| self.kinematic_viscosity(h),
+                ),
+                TimeStep::Block(_) => unreachable!("handled by block_step"),
+            }
+        };
+
+        let (integrator, dt) = (self.integrator, self.time_step);
+        self.cached_acceleration = integrator.step(self, &acceleration, dt);
+        if let (Some(laminar), Some(implicit)) = (&self.laminar_model, &self.implicit_viscosity) {
+            let _scope = profiler::scope(Phase::Viscosity.name());
+            laminar.update_viscosity(&mut self.space);
+            self.viscosity_convergence = Some(implicit.solve(&mut self.space, dt));
+            self.space
+                .particles_mut()
+                .filter(|p| p.is_solid())
+                .for_each(|p| p.velocity = Vector::ZERO);
+            // the cached forces saw the velocity before the solve
+            self.cached_acceleration = None;
+        }
+        // alpha moves slowly, the cached forces keep the one they were computed with
+        self.viscosity_model.update_alpha(&mut self.space, dt);
+        self.update_temperature(dt);
+        self.t += dt;
     }
 
-    pub fn get_display_distance(&self) -> f32 {
+    /// Advance to the next time any particle ends its step, every particle drifts but only
+    /// the ones ending their step get new forces and are kicked.
+    fn block_step(&mut self, block: &time_step::Block) {
+        let mut state = match self.block_state.take() {
+            Some(state) => state,
+            None => self.start_block(block),
+        };
+
+        let next = state
+            .level
+            .iter()
+            .map(|&level| {
+                let ticks = block.ticks(level);
+                state.tick - state.tick % ticks + ticks
+            })
+            .min()
+            .unwrap_or(block.ticks(0));
+        let dt = (next - state.tick) as Real * state.max_step / block.ticks(0) as Real;
+
+        // velocities are half a step ahead, so this is the drift of the leapfrog
+        let velocity = self.velocity();
+        let position = izip!(self.position(), self.drift_velocity(&velocity))
+            .map(|(x, v)| x + v * dt)
+            .collect::<Vec<_>>();
+        let density = izip!(self.density(), self.density_rate())
+            .map(|(rho, rate)| rho + rate * dt)
+            .collect::<Vec<_>>();
+        self.set_density(&density);
+        self.set_state(&position, &velocity);
+
+        let active = state
+            .level
+            .iter()
+            .map(|&level| next.is_multiple_of(block.ticks(level)))
+            .collect();
+        self.space.set_active(Some(active));
+        let acceleration = self.acceleration();
+        let stable = self.stable_time_step(block, &acceleration);
+        self.kick(block, &mut state, &acceleration, &stable, next, true);
+        self.space.set_active(None);
+        self.viscosity_model.update_alpha(&mut self.space, dt);
+        self.update_temperature(dt);
+
+        state.tick = next % block.ticks(0);
+        self.time_step = dt;
+        self.t += dt;
+        self.block_state = Some(state);
+    }
+
+    fn start_block(&mut self, block: &time_step::Block) -> BlockState {
+        let acceleration = self.acceleration();
+        let stable = self.stable_time_step(block, &acceleration);
+        let mut state = BlockState {
+            level: vec![0; self.space.len()],
+            tick: 0,
+            max_step: 0.,
+            active_count: 0,
+            unstable_count: 0,
+        };
+        self.kick(block, &mut state, &acceleration, &stable, 0, false);
+        state
+    }
+
+    fn stable_time_step(&self, block: &time_step::Block, acceleration: &[Vector]) -> Vec<Real> {
+        let _scope = profiler::scope(Phase::TimeStep.name());
+        block.adaptive.per_particle::<CubicSpline>(
+            &self.space,
+            acceleration,
+            self.speed_of_sound,
+            |h| self.kinematic_viscosity(h),
+        )
+    }
+
+    // every particle follows its neighbours' temperature, whatever its step
+    fn update_temperature(&mut self, dt: Real) {
+        if let Some(conduction) = &self.conduction {
+            let _scope = profiler::scope(Phase::Heat.name());
+            conduction.update_temperature(&mut self.space, self.boundary.as_ref(), dt);
+        }
+        if let Some(phase_change) = &self.phase_change {
+            let _scope = profiler::scope(Phase::Heat.name());
+            phase_change.update_phase(&mut self.space);
+            // forces on particles that just froze or melted are out of date
+            self.cached_acceleration = None;
+        }
+    }
+
+    fn lightest_rest_density(&self) -> Real {
+        let density = self.materials.iter().map(Material::rest_density);
+        density.fold(Real::INFINITY, Real::min)
+    }
+
+    // artificial, explicit physical and eddy viscosity together, the physical one at the
+    // lightest rest density. Heat diffuses under the same limit.
+    fn kinematic_viscosity(&self, h: Real) -> Real {
+        let laminar = match (&self.laminar_model, &self.implicit_viscosity) {
+            (Some(laminar), None) => laminar.kinematic_viscosity(self.lightest_rest_density()),
+            _ => 0.,
+        };
+        let viscosity = self.viscosity_model.kinematic_viscosity(h) + laminar + self.eddy_viscosity;
+        match &self.conduction {
+            Some(conduction) => viscosity.max(conduction.diffusivity(self.lightest_rest_density())),
+            None => viscosity,
+        }
+    }
+
+    // Close the step of every active particle with a half kick, pick its next level
+    // and open the next step with another half kick.
+    fn kick(
+        &mut self,
+        block: &time_step::Block,
+        state: &mut BlockState,
+        acceleration: &[Vector],
+        stable: &[Real],
+        tick: u64,
+        closing: bool,
+    ) {
+        let _scope = profiler::scope(Phase::Integration.name());
+        let previous = state.level.clone();
+        let close_length = state.max_step / block.ticks(0) as Real;
+
+        // every particle ends its step at the end of a block, so the levels can follow the
+        // stable steps of the flow as it speeds up or slows down
+        if tick.is_multiple_of(block.ticks(0)) {
+            state.max_step = self
+                .space
+                .active_particles()
+                .map(|p| stable[p.id])
+                .fold(0., Real::max);
+        }
+        let tick_length = state.max_step / block.ticks(0) as Real;
+
+        // a particle can only move to a level whose steps start at `tick`
+        let mut level = previous.clone();
+        let mut unstable_count = 0;
+        self.space.active_particles().for_each(|p| {
+            let mut l = block.level(state.max_step, stable[p.id]);
+            while !tick.is_multiple_of(block.ticks(l)) {
+                l += 1;
+            }
+            level[p.id] = l;
+            unstable_count += (stable[p.id] < tick_length) as usize;
+        });
+        state.unstable_count = unstable_count;
+
+        // a step may not be much longer than the one of any neighbour
+        self.space.active_particles().for_each(|a| {
+            let radius = CubicSpline::new(a.kernel_radius).support_radius();
+            let finest = self
+                .space
+                .neighbour(a, radius)
+                .map(|b| level[b.id])
+                .max()
+                .unwrap_or(0);
+            state.level[a.id] = level[a.id].max(finest.saturating_sub(block.neighbour_gap));
+        });
+
+        let mut active_count = 0;
+        self.space.active_particles_mut().for_each(|p| {
+            let close = match closing {
+                true => block.ticks(previous[p.id]) as Real * close_length,
+                false => 0.,
+            };
+            let open = block.ticks(state.level[p.id]) as Real * tick_length;
+            p.velocity += acceleration[p.id] * (close + open) / 2.;
+            active_count += 1;
+        });
+        state.active_count = active_count;
+    }
+
+    pub fn get_display_distance(&self) -> Real {
         self.display_distance
     }
 
@@ -117,7 +663,394 @@ impl Simulator {
         &self.space
     }
 
-    pub fn get_time(&self) -> f32 {
+    /// Particles of the solid, if the scene has one.
+    pub fn get_solid(&self) -> Option<&Space> {
+        self.boundary.as_ref().map(|boundary| boundary.solid())
+    }
+
+    pub fn get_time(&self) -> Real {
         self.t
     }
+
+    /// Time step taken by the last `update`, or the initial estimate before the first one.
+    pub fn get_time_step(&self) -> Real {
+        self.time_step
+    }
+
+    pub fn get_step_timing(&self) -> &StepTiming {
+        &self.step_timing
+    }
+
+    /// Keep the events of every step from now on, see `take_trace`.
+    pub fn start_trace(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    /// Events recorded since the last call, empty if `start_trace` was never called.
+    pub fn take_trace(&mut self) -> Vec<Event> {
+        self.trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Particles kicked by the last `update`, every particle unless block time steps are used.
+    pub fn get_active_count(&self) -> usize {
+        self.block_state
+            .as_ref()
+            .map_or(self.space.len(), |state| state.active_count)
+    }
+
+    /// Particles kicked by the last `update` with a step longer than their stable one, because
+    /// they need a level finer than the block allows. Always zero without block time steps.
+    pub fn get_unstable_count(&self) -> usize {
+        self.block_state
+            .as_ref()
+            .map_or(0, |state| state.unstable_count)
+    }
+
+    pub fn get_smoothing_length(&self) -> density::SmoothingLength {
+        self.smoothing_length
+    }
+
+    /// Outcome of the last implicit viscosity solve.
+    pub fn get_viscosity_convergence(&self) -> Option<viscosity::Convergence> {
+        self.viscosity_convergence
+    }
+
+    /// Largest sub-particle-scale eddy viscosity in cm^2/s, zero without turbulence.
+    pub fn get_eddy_viscosity(&self) -> Real {
+        self.eddy_viscosity
+    }
+
+    /// Time, step and solver state of the last `update`, one line each.
+    pub fn status(&self) -> Vec<String> {
+        let solve = match self.get_viscosity_convergence() {
+            Some(c) => format!(
+                "viscosity solve: {} iterations, residual {:.1e}{}",
+                c.iterations,
+                c.residual,
+                if c.converged { "" } else { ", not converged" }
+            ),
+            None => "viscosity solve: explicit".to_string(),
+        };
+        let solve = match self.get_eddy_viscosity() {
+            nu if nu > 0. => format!("{solve}, eddy viscosity up to {nu:.2e} cm^2/s"),
+            _ => solve,
+        };
+        let step = match self.get_unstable_count() {
+            0 => String::new(),
+            n => format!(", {n} unstable"),
+        };
+        vec![
+            format!(
+                "t: {:.4} s, dt: {:.2e} s, {} active{step}",
+                self.get_time(),
+                self.get_time_step(),
+                self.get_active_count()
+            ),
+            format!("h: {}", self.get_smoothing_length()),
+            solve,
+        ]
+    }
+
+    // snapshot of the particles and their neighbours for the `Soa` and `Simd` pair loops
+    fn neighbour_list(&self) -> (ParticleSoa, NeighbourList) {
+        let _scope = profiler::scope(Phase::NeighbourSearch.name());
+        let soa = self.space.to_soa();
+        let neighbours = NeighbourList::new::<CubicSpline>(&soa);
+        (soa, neighbours)
+    }
+}
+
+impl System for Simulator {
+    fn position(&self) -> Vec<Vector> {
+        self.space.collect_by_id(|p| p.position)
+    }
+
+    fn velocity(&self) -> Vec<Vector> {
+        self.space.collect_by_id(|p| p.velocity)
+    }
+
+    fn set_state(&mut self, position: &[Vector], velocity: &[Vector]) {
+        {
+            let _scope = profiler::scope(Phase::Integration.name());
+            // solids keep still whatever the integrator made of them
+            self.space
+                .particles_mut()
+                .filter(|p| !p.is_solid())
+                .for_each(|p| {
+                    p.position = position[p.id];
+                    p.velocity = velocity[p.id];
+                });
+        }
+
+        let _scope = profiler::scope(Phase::SpaceUpdate.name());
+        self.space.update();
+    }
+
+    fn acceleration(&mut self) -> Vec<Vector> {
+        {
+            let _scope = profiler::scope(Phase::Density.name());
+            match (&self.continuity_model, &self.grad_h_model) {
+                (None, Some(grad_h)) => _ = grad_h.update_density(&mut self.space),
+                (None, None) if self.pair_loop == PairLoop::Space => {
+                    self.density_model.update_density(&mut self.space)
+                }
+                (None, None) => {
+                    let (soa, neighbours) = self.neighbour_list();
+                    let density = match self.pair_loop {
+                        PairLoop::Simd => self.density_model.density_simd(&soa, &neighbours),
+                        _ => self.density_model.density_soa(&soa, &neighbours),
+                    };
+                    self.density_model.set_density(&mut self.space, &density);
+                }
+                (Some(continuity), _) => {
+                    continuity.update_kernel_radius(&mut self.space);
+                    let mut rate = continuity.density_rate(&self.space);
+                    if let Some(delta_sph) = &self.delta_sph {
+                        let diffusion = delta_sph.density_diffusion(&self.space);
+                        rate.iter_mut().zip(diffusion).for_each(|(r, d)| *r += d);
+                    }
+                    self.space
+                        .active_particles()
+                        .zip(rate)
+                        .for_each(|(p, r)| self.density_rate[p.id] = r);
+                }
+            }
+        }
+
+        // the kernel radii are final now, pressure and viscosity share the list
+        let neighbours = match self.pair_loop {
+            PairLoop::Space => None,
+            PairLoop::Soa | PairLoop::Simd => Some(self.neighbour_list().1),
+        };
+        let pressure_acc = {
+            let _scope = profiler::scope(Phase::Pressure.name());
+            self.pressure_model.update_pressure(&mut self.space);
+            match &neighbours {
+                None => self.pressure_model.accelration(&self.space),
+                Some(neighbours) => {
+                    let soa = self.space.to_soa();
+                    let acceleration = match self.pair_loop {
+                        PairLoop::Simd => self.pressure_model.accelration_simd(&soa, neighbours),
+                        _ => self.pressure_model.accelration_soa(&soa, neighbours),
+                    };
+                    self.space.active_by_id(&acceleration)
+                }
+            }
+        };
+        let viscosity_acc = {
+            let _scope = profiler::scope(Phase::Viscosity.name());
+            self.viscosity_model.update_balsara(&mut self.space);
+            let mut acceleration = match &neighbours {
+                None => self.viscosity_model.accelration(&self.space),
+                Some(neighbours) => {
+                    let soa = self.space.to_soa();
+                    let acceleration = match self.pair_loop {
+                        PairLoop::Simd => self.viscosity_model.accelration_simd(&soa, neighbours),
+                        _ => self.viscosity_model.accelration_soa(&soa, neighbours),
+                    };
+                    self.space.active_by_id(&acceleration)
+                }
+            };
+            if let (Some(laminar), None) = (&self.laminar_model, &self.implicit_viscosity) {
+                laminar.update_viscosity(&mut self.space);
+                let laminar = laminar.accelration(&self.space);
+                acceleration
+                    .iter_mut()
+                    .zip(laminar)
+                    .for_each(|(a, l)| *a += l);
+            }
+            if let Some(turbulence) = &self.turbulence_model {
+                let (turbulence, eddy_viscosity) = turbulence.accelration(&self.space);
+                self.eddy_viscosity = eddy_viscosity;
+                acceleration
+                    .iter_mut()
+                    .zip(turbulence)
+                    .for_each(|(a, t)| *a += t);
+            }
+            self.vorticity_model.update_vorticity(&mut self.space);
+            let confinement = self.vorticity_model.accelration(&self.space);
+            acceleration
+                .iter_mut()
+                .zip(confinement)
+                .for_each(|(a, c)| *a += c);
+            acceleration
+        };
+        let surface_tension_acc = {
+            let _scope = profiler::scope(Phase::SurfaceTension.name());
+            match (&self.csf_model, &self.akinci_model, &self.boundary) {
+                (Some(csf), _, _) => csf.accelration(&self.space, self.boundary.as_ref()),
+                (None, Some(akinci), Some(boundary)) => {
+                    let adhesion = akinci.adhesion(&self.space, boundary);
+                    izip!(akinci.accelration(&self.space), adhesion)
+                        .map(|(a, b)| a + b)
+                        .collect()
+                }
+                (None, Some(akinci), None) => akinci.accelration(&self.space),
+                (None, None, _) => self.surface_tension_model.accelration(&self.space),
+            }
+        };
+        let boundary_acc = match &self.boundary {
+            Some(boundary) => boundary.accelration(&self.space),
+            None => vec![Vector::ZERO; surface_tension_acc.len()],
+        };
+
+        let acceleration = izip!(
+            self.space.active_particles(),
+            pressure_acc,
+            viscosity_acc,
+            surface_tension_acc,
+            boundary_acc
+        )
+        .map(
+            |(p, pressure, viscosity, surface_tension, boundary)| match p.is_solid() {
+                true => Vector::ZERO,
+                false => pressure + viscosity + surface_tension + boundary + self.gravity,
+            },
+        )
+        .collect();
+        self.space.order_by_id(acceleration)
+    }
+
+    fn drift_velocity(&self, velocity: &[Vector]) -> Vec<Vector> {
+        match &self.xsph {
+            Some(xsph) => xsph.drift_velocity(&self.space, velocity),
+            None => velocity.to_vec(),
+        }
+    }
+
+    fn density(&self) -> Vec<Real> {
+        match self.continuity_model {
+            Some(_) => self.space.collect_by_id(|p| p.density),
+            None => vec![],
+        }
+    }
+
+    fn set_density(&mut self, density: &[Real]) {
+        if density.is_empty() {
+            return;
+        }
+        self.space
+            .particles_mut()
+            .for_each(|p| p.density = density[p.id]);
+    }
+
+    fn density_rate(&self) -> Vec<Real> {
+        match self.continuity_model {
+            Some(_) => self.density_rate.clone(),
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+
+    fn block_scene(block_levels: Option<u32>) -> Scene {
+        Scene {
+            particle_per_side: 5,
+            block_levels,
+            ..Default::default()
+        }
+    }
+
+    // total momentum over the momentum the particles would have moving apart
+    fn momentum_error(space: &Space) -> Real {
+        let momentum = space
+            .particles()
+            .fold(Vector::ZERO, |m, p| m + p.velocity * p.mass);
+        let scale: Real = space
+            .particles()
+            .map(|p| p.velocity.length() * p.mass)
+            .sum();
+        momentum.length() / scale
+    }
+
+    #[test]
+    fn block_step_inactive_drift() {
+        let mut sim = Simulator::new(block_scene(Some(3)));
+        sim.update();
+        let mut drifted = 0;
+        let mut max_step = vec![];
+        for _ in 0..40 {
+            let state = sim.block_state.as_ref().unwrap();
+            let (level, tick) = (state.level.clone(), state.tick);
+            max_step.push(state.max_step);
+            let (position, velocity) = (sim.position(), sim.velocity());
+            sim.update();
+
+            let TimeStep::Block(block) = &sim.time_stepping else {
+                unreachable!()
+            };
+            let next = match sim.block_state.as_ref().unwrap().tick {
+                0 => block.ticks(0),
+                next => next,
+            };
+            assert!(next > tick);
+            let dt = sim.get_time_step();
+            let inactive = (0..sim.space.len())
+                .filter(|&id| !next.is_multiple_of(block.ticks(level[id])))
+                .collect_vec();
+            assert_eq!(sim.get_active_count(), sim.space.len() - inactive.len());
+            inactive.iter().for_each(|&id| {
+                assert_eq!(sim.velocity()[id], velocity[id]);
+                let drift = position[id] + velocity[id] * dt;
+                assert!((sim.position()[id] - drift).length() <= 1e-6);
+            });
+            drifted += inactive.len();
+            assert!(momentum_error(&sim.space) < 1e-2);
+        }
+        assert!(drifted > 0, "every particle stayed on level 0");
+        // the flow speeds up from rest, every block starts with a shorter step
+        assert!(max_step.first() > max_step.last());
+        assert_eq!(sim.get_unstable_count(), 0);
+    }
+
+    #[test]
+    fn block_step_single_level() {
+        let mut block = Simulator::new(block_scene(Some(0)));
+        let mut global = Simulator::new(block_scene(None));
+        for _ in 0..20 {
+            block.update();
+            // the same steps as the block, taken by the global kick-drift-kick
+            global.time_stepping = TimeStep::Fixed(block.get_time_step());
+            global.update();
+
+            assert_eq!(block.get_active_count(), block.space.len());
+            assert!(momentum_error(&block.space) < 1e-5);
+            assert_eq!(block.get_time(), global.get_time());
+            izip!(block.position(), global.position())
+                .for_each(|(a, b)| assert!((a - b).length() <= 1e-4));
+        }
+    }
+
+    #[test]
+    fn pair_loops_match() {
+        for block_levels in [None, Some(3)] {
+            let scene = |pair_loop| Scene {
+                balsara: true,
+                pair_loop,
+                ..block_scene(block_levels)
+            };
+            let mut expect = Simulator::new(scene(PairLoop::Space));
+            let mut sims = [PairLoop::Soa, PairLoop::Simd].map(|p| Simulator::new(scene(p)));
+            for _ in 0..10 {
+                expect.update();
+                for sim in &mut sims {
+                    sim.update();
+                    assert_eq!(sim.get_active_count(), expect.get_active_count());
+                    izip!(sim.position(), expect.position())
+                        .for_each(|(a, b)| assert!((a - b).length() <= 1e-4, "{a} {b}"));
+                }
+            }
+            // the sums run in another order, compare against the largest acceleration
+            let expect = expect.acceleration();
+            let scale = expect.iter().map(|a| a.length()).fold(0., Real::max);
+            for sim in &mut sims {
+                izip!(sim.acceleration(), &expect)
+                    .for_each(|(a, b)| assert!((a - *b).length() <= 1e-4 * scale, "{a} {b}"));
+            }
+        }
+    }
 }
@@ -0,0 +1,206 @@
+use crate::kernel::Kernel;
+use crate::profiler;
+use crate::real::*;
+use crate::util_3d::*;
+
+/// Stability limits evaluated every step, the smallest one wins.
+#[derive(Debug, Clone)]
+pub struct Adaptive {
+    /// Courant number on `h / v_signal`.
+    pub cfl: Real,
+    /// Coefficient of `sqrt(h / |a|)`.
+    pub force: Real,
+    /// Coefficient of `h^2 / nu`.
+    pub viscous: Real,
+    pub min: Real,
+    pub max: Real,
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Self {
+            cfl: 0.4,
+            force: 0.25,
+            viscous: 0.125,
+            min: 0.,
+            max: Real::INFINITY,
+        }
+    }
+}
+
+impl Adaptive {
+    /// `acceleration` is indexed by particle id,
+    /// `kinematic_viscosity` is evaluated for each particle with its own kernel radius.
+    pub fn compute<T: Kernel>(
+        &self,
+        space: &Space,
+        acceleration: &[Vector],
+        speed_of_sound: Real,
+        kinematic_viscosity: impl Fn(Real) -> Real,
+    ) -> Real {
+        self.per_particle::<T>(space, acceleration, speed_of_sound, kinematic_viscosity)
+            .into_iter()
+            .fold(Real::INFINITY, Real::min)
+            .clamp(self.min, self.max)
+    }
+
+    /// Stable step of every active particle by id, inactive particles get infinity.
+    pub fn per_particle<T: Kernel>(
+        &self,
+        space: &Space,
+        acceleration: &[Vector],
+        speed_of_sound: Real,
+        kinematic_viscosity: impl Fn(Real) -> Real,
+    ) -> Vec<Real> {
+        let _scope = profiler::scope("Adaptive::per_particle");
+        let mut dt = vec![Real::INFINITY; space.len()];
+        space.active_particles().for_each(|a| {
+            let acc = acceleration[a.id];
+            let h = a.kernel_radius;
+            let signal_velocity = speed_of_sound + max_approach_velocity::<T>(space, a);
+            let cfl = self.cfl * h / signal_velocity;
+
+            let force = match acc.length() {
+                x if x > 0. => self.force * (h / x).sqrt(),
+                _ => Real::INFINITY,
+            };
+
+            let viscous = match kinematic_viscosity(h) {
+                nu if nu > 0. => self.viscous * h.powi(2) / nu,
+                _ => Real::INFINITY,
+            };
+            dt[a.id] = cfl.min(force).min(viscous).clamp(self.min, self.max);
+        });
+        dt
+    }
+}
+
+// max of h v_ab.r_ab / (r^2 + 0.01 h^2) over approaching neighbours (Monaghan 1992)
+fn max_approach_velocity<T: Kernel>(space: &Space, a: &Particle) -> Real {
+    let kernel = T::new(a.kernel_radius);
+    space
+        .neighbour(a, kernel.support_radius())
+        .map(|b| {
+            let r = a.position - b.position;
+            let v = a.velocity - b.velocity;
+            let h = (a.kernel_radius + b.kernel_radius) / 2.;
+            -(h * r.dot(v) / (r.length_squared() + 0.01 * h.powi(2))).min(0.)
+        })
+        .fold(0., Real::max)
+}
+
+/// Power-of-two block time steps, a particle at `level` advances by `max_step / 2^level`.
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub adaptive: Adaptive,
+    /// The smallest step is `max_step / 2^max_level`.
+    pub max_level: u32,
+    /// Largest level difference allowed between neighbours (Saitoh & Makino 2009).
+    pub neighbour_gap: u32,
+}
+
+impl Block {
+    pub fn new(adaptive: Adaptive, max_level: u32) -> Self {
+        Self {
+            adaptive,
+            max_level,
+            neighbour_gap: 2,
+        }
+    }
+
+    /// Coarsest level whose step fits in `dt`, capped at `max_level`. Particles the cap leaves
+    /// with too long a step show in `Simulator::get_unstable_count`.
+    pub fn level(&self, max_step: Real, dt: Real) -> u32 {
+        let level = (max_step / dt).log2().ceil().max(0.);
+        (level as u32).min(self.max_level)
+    }
+
+    /// Length of a step at `level` in units of the smallest step.
+    pub fn ticks(&self, level: u32) -> u64 {
+        1 << (self.max_level - level)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TimeStep {
+    Fixed(Real),
+    Adaptive(Adaptive),
+    Block(Block),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::CubicSpline;
+
+    #[test]
+    fn shrink_with_motion() {
+        let h = 1.3;
+        let speed_of_sound = 10.;
+        let policy = Adaptive {
+            min: 1e-4,
+            max: 1.,
+            ..Default::default()
+        };
+
+        let particles = init_setup::create_cube(1., 4, Vector::ZERO, 1., h);
+        let count = particles.len();
+        let still = Space::new(h, particles.clone());
+        let at_rest = policy.compute::<CubicSpline>(
+            &still,
+            &vec![Vector::ZERO; count],
+            speed_of_sound,
+            |_| 0.,
+        );
+        assert!((at_rest - policy.cfl * h / speed_of_sound).abs() <= 1e-6);
+
+        // particles moving toward the centre raise the signal velocity
+        let moving = particles
+            .into_iter()
+            .map(|mut p| {
+                p.velocity = -p.position * 5.;
+                p
+            })
+            .collect();
+        let moving = Space::new(h, moving);
+        let approach = policy.compute::<CubicSpline>(
+            &moving,
+            &vec![Vector::ZERO; count],
+            speed_of_sound,
+            |_| 0.,
+        );
+        assert!(approach < at_rest);
+
+        let accelerating = policy.compute::<CubicSpline>(
+            &still,
+            &vec![Vector::X * 1e3; count],
+            speed_of_sound,
+            |_| 0.,
+        );
+        assert!((accelerating - policy.force * (h / 1e3).sqrt()).abs() <= 1e-6);
+
+        let viscous = policy.compute::<CubicSpline>(
+            &still,
+            &vec![Vector::ZERO; count],
+            speed_of_sound,
+            |h| 1e3 * h,
+        );
+        assert!((viscous - policy.viscous * h / 1e3).abs() <= 1e-6);
+
+        let clamped =
+            policy.compute::<CubicSpline>(&still, &vec![Vector::X * 1e12; count], 1., |_| 0.);
+        assert_eq!(clamped, policy.min);
+    }
+
+    #[test]
+    fn block_level() {
+        let block = Block::new(Adaptive::default(), 4);
+        assert_eq!(block.level(1., 2.), 0);
+        assert_eq!(block.level(1., 1.), 0);
+        assert_eq!(block.level(1., 0.3), 2);
+        assert_eq!(block.level(1., 0.25), 2);
+        assert_eq!(block.level(1., 1e-9), 4);
+        assert_eq!(block.ticks(0), 16);
+        assert_eq!(block.ticks(4), 1);
+    }
+}
@@ -1,19 +1,21 @@
 use std::{
-    f32::consts::PI,
     iter,
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use super::Particle;
+use crate::real::{consts::PI, *};
 use itertools::{iproduct, izip, Itertools};
-use macroquad::{
-    prelude::*,
-    rand::{gen_range, srand},
-};
+use macroquad::rand::{gen_range, srand};
+
+// ids match the index `Space::new` will give them
+fn numbered((id, particle): (usize, Particle)) -> Particle {
+    Particle { id, ..particle }
+}
 
-pub fn diagonal_test(mass: f32, default_kernel_radius: f32) -> Vec<Particle> {
-    let position = vec![Vec3::NEG_ONE, Vec3::ZERO, Vec3::ONE];
-    let velocity = vec![Vec3::ONE, Vec3::ZERO, Vec3::NEG_ONE];
+pub fn diagonal_test(mass: Real, default_kernel_radius: Real) -> Vec<Particle> {
+    let position = vec![Vector::NEG_ONE, Vector::ZERO, Vector::ONE];
+    let velocity = vec![Vector::ONE, Vector::ZERO, Vector::NEG_ONE];
     izip!(
         position,
         velocity,
@@ -21,15 +23,17 @@ pub fn diagonal_test(mass: f32, default_kernel_radius: f32) -> Vec<Particle> {
         iter::repeat(default_kernel_radius),
     )
     .map(Into::into)
+    .enumerate()
+    .map(numbered)
     .collect()
 }
 
 pub fn random_points(
     count: usize,
-    low: f32,
-    high: f32,
-    mass: f32,
-    default_kernel_radius: f32,
+    low: Real,
+    high: Real,
+    mass: Real,
+    default_kernel_radius: Real,
 ) -> Vec<Particle> {
     srand(
         SystemTime::now()
@@ -39,70 +43,130 @@ pub fn random_points(
     );
 
     let random_value = || gen_range(low, high);
-    let random_pos = || vec3(random_value(), random_value(), random_value());
+    let random_pos = || vector(random_value(), random_value(), random_value());
     let mut position = (0..count).map(|_| random_pos()).collect_vec();
     izip!(
         position,
-        iter::repeat(Vec3::ZERO),
+        iter::repeat(Vector::ZERO),
         iter::repeat(mass),
         iter::repeat(default_kernel_radius),
     )
     .map(Into::into)
+    .enumerate()
+    .map(numbered)
     .collect()
 }
 
 pub fn create_cube(
-    spacing: f32,
+    spacing: Real,
     particle_per_side: isize,
-    center_offset: Vec3,
-    mass: f32,
-    default_kernel_radius: f32,
+    center_offset: Vector,
+    mass: Real,
+    default_kernel_radius: Real,
 ) -> Vec<Particle> {
-    let center = center_offset + (spacing * (particle_per_side - 1) as f32) / 2. * Vec3::NEG_ONE;
+    let center = center_offset + (spacing * (particle_per_side - 1) as Real) / 2. * Vector::NEG_ONE;
 
     let position = iproduct!(
         0..particle_per_side,
         0..particle_per_side,
         0..particle_per_side
     )
-    .map(|(i, j, k)| center + vec3(i as f32, j as f32, k as f32) * spacing);
+    .map(|(i, j, k)| center + vector(i as Real, j as Real, k as Real) * spacing);
+
+    izip!(
+        position,
+        iter::repeat(Vector::ZERO),
+        iter::repeat(mass),
+        iter::repeat(default_kernel_radius),
+    )
+    .map(Into::into)
+    .enumerate()
+    .map(numbered)
+    .collect_vec()
+}
+
+/// Square layer of `particle_per_side^2` particles in the xy plane.
+pub fn create_plate(
+    spacing: Real,
+    particle_per_side: isize,
+    center_offset: Vector,
+    mass: Real,
+    default_kernel_radius: Real,
+) -> Vec<Particle> {
+    let half = (spacing * (particle_per_side - 1) as Real) / 2.;
+    let corner = center_offset - vector(half, half, 0.);
+
+    let position = iproduct!(0..particle_per_side, 0..particle_per_side)
+        .map(|(i, j)| corner + vector(i as Real, j as Real, 0.) * spacing);
+
+    izip!(
+        position,
+        iter::repeat(Vector::ZERO),
+        iter::repeat(mass),
+        iter::repeat(default_kernel_radius),
+    )
+    .map(Into::into)
+    .enumerate()
+    .map(numbered)
+    .collect_vec()
+}
+
+/// Open box of one layer around a `create_cube` with the same arguments, one `spacing`
+/// away from it and twice as high, so the fluid can slosh without spilling.
+pub fn create_container(
+    spacing: Real,
+    particle_per_side: isize,
+    center_offset: Vector,
+    mass: Real,
+    default_kernel_radius: Real,
+) -> Vec<Particle> {
+    let n = particle_per_side;
+    let corner = center_offset + (spacing * (n - 1) as Real) / 2. * Vector::NEG_ONE;
+
+    let position = iproduct!(-1..=n, -1..=n, -1..2 * n)
+        .filter(|&(i, j, k)| i == -1 || i == n || j == -1 || j == n || k == -1)
+        .map(|(i, j, k)| corner + vector(i as Real, j as Real, k as Real) * spacing);
 
     izip!(
         position,
-        iter::repeat(Vec3::ZERO),
+        iter::repeat(Vector::ZERO),
         iter::repeat(mass),
         iter::repeat(default_kernel_radius),
     )
     .map(Into::into)
+    .enumerate()
+    .map(numbered)
     .collect_vec()
 }
 
 pub fn create_sphere(
-    mass: f32,
-    radius: f32,
+    mass: Real,
+    radius: Real,
     count: usize,
-    center_offset: Vec3,
-    default_kernel_radius: f32,
+    center_offset: Vector,
+    default_kernel_radius: Real,
 ) -> Vec<Particle> {
-    let golden_ratio = (1.0 + f32::sqrt(5.0)) / 2.0;
+    let golden_ratio = (1.0 + Real::sqrt(5.0)) / 2.0;
     let angle_increment = PI * (2.0 / golden_ratio);
 
     let position = (0..count).map(|i| {
-        let y = (1.0 - (i as f32) / (count as f32) * 2.0) * radius;
+        let y = (1.0 - (i as Real) / (count as Real) * 2.0) * radius;
         let radius_at_y = (radius.powi(2) - y.powi(2)).sqrt();
-        let phi = i as f32 * angle_increment;
+        let phi = i as Real * angle_increment;
 
         let x = phi.cos() * radius_at_y;
         let z = phi.sin() * radius_at_y;
-        vec3(x, y, z) + center_offset
+        vector(x, y, z) + center_offset
     });
     izip!(
         position,
-        iter::repeat(Vec3::ZERO),
+        iter::repeat(Vector::ZERO),
         iter::repeat(mass),
         iter::repeat(default_kernel_radius),
     )
     .map(Into::into)
+    .enumerate()
+    .map(numbered)
     .collect_vec()
 }
 
@@ -112,16 +176,16 @@ mod tests {
 
     #[test]
     fn even_side_cube() {
-        let particles = create_cube(1., 2, Vec3::ZERO, 1., 1.);
+        let particles = create_cube(1., 2, Vector::ZERO, 1., 1.);
         let expect = vec![
-            vec3(-0.5, -0.5, -0.5),
-            vec3(-0.5, -0.5, 0.5),
-            vec3(-0.5, 0.5, -0.5),
-            vec3(-0.5, 0.5, 0.5),
-            vec3(0.5, -0.5, -0.5),
-            vec3(0.5, -0.5, 0.5),
-            vec3(0.5, 0.5, -0.5),
-            vec3(0.5, 0.5, 0.5),
+            vector(-0.5, -0.5, -0.5),
+            vector(-0.5, -0.5, 0.5),
+            vector(-0.5, 0.5, -0.5),
+            vector(-0.5, 0.5, 0.5),
+            vector(0.5, -0.5, -0.5),
+            vector(0.5, -0.5, 0.5),
+            vector(0.5, 0.5, -0.5),
+            vector(0.5, 0.5, 0.5),
         ];
         let position = particles.into_iter().map(|p| p.position).collect_vec();
 
@@ -129,41 +193,41 @@ mod tests {
         assert_eq!(position.len(), 8);
         position.into_iter().zip(expect).for_each(|(a, b)| {
             let len = (a - b).length();
-            assert!(len <= f32::EPSILON, "left: {:?}, right: {:?}", a, b);
+            assert!(len <= Real::EPSILON, "left: {:?}, right: {:?}", a, b);
         });
     }
 
     #[test]
     fn odd_side_cube() {
-        let particles = create_cube(1., 3, Vec3::ZERO, 1., 1.);
+        let particles = create_cube(1., 3, Vector::ZERO, 1., 1.);
         let expect = vec![
-            vec3(-1., -1., -1.),
-            vec3(-1., -1., 0.),
-            vec3(-1., -1., 1.),
-            vec3(-1., 0., -1.),
-            vec3(-1., 0., 0.),
-            vec3(-1., 0., 1.),
-            vec3(-1., 1., -1.),
-            vec3(-1., 1., 0.),
-            vec3(-1., 1., 1.),
-            vec3(0., -1., -1.),
-            vec3(0., -1., 0.),
-            vec3(0., -1., 1.),
-            vec3(0., 0., -1.),
-            vec3(0., 0., 0.),
-            vec3(0., 0., 1.),
-            vec3(0., 1., -1.),
-            vec3(0., 1., 0.),
-            vec3(0., 1., 1.),
-            vec3(1., -1., -1.),
-            vec3(1., -1., 0.),
-            vec3(1., -1., 1.),
-            vec3(1., 0., -1.),
-            vec3(1., 0., 0.),
-            vec3(1., 0., 1.),
-            vec3(1., 1., -1.),
-            vec3(1., 1., 0.),
-            vec3(1., 1., 1.),
+            vector(-1., -1., -1.),
+            vector(-1., -1., 0.),
+            vector(-1., -1., 1.),
+            vector(-1., 0., -1.),
+            vector(-1., 0., 0.),
+            vector(-1., 0., 1.),
+            vector(-1., 1., -1.),
+            vector(-1., 1., 0.),
+            vector(-1., 1., 1.),
+            vector(0., -1., -1.),
+            vector(0., -1., 0.),
+            vector(0., -1., 1.),
+            vector(0., 0., -1.),
+            vector(0., 0., 0.),
+            vector(0., 0., 1.),
+            vector(0., 1., -1.),
+            vector(0., 1., 0.),
+            vector(0., 1., 1.),
+            vector(1., -1., -1.),
+            vector(1., -1., 0.),
+            vector(1., -1., 1.),
+            vector(1., 0., -1.),
+            vector(1., 0., 0.),
+            vector(1., 0., 1.),
+            vector(1., 1., -1.),
+            vector(1., 1., 0.),
+            vector(1., 1., 1.),
         ];
 
         let position = particles.into_iter().map(|p| p.position).collect_vec();
@@ -171,7 +235,7 @@ mod tests {
         assert_eq!(position.len(), 27);
         position.into_iter().zip(expect).for_each(|(a, b)| {
             let len = (a - b).length();
-            assert!(len <= f32::EPSILON, "left: {:?}, right: {:?}", a, b);
+            assert!(len <= Real::EPSILON, "left: {:?}, right: {:?}", a, b);
         });
     }
 }
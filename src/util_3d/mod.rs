@@ -1,8 +1,12 @@
 pub mod init_setup;
+mod neighbour_list;
 mod particle;
+mod particle_soa;
 mod space;
 mod spatial_hash_grid;
 
+pub use neighbour_list::NeighbourList;
 pub use particle::Particle;
+pub use particle_soa::ParticleSoa;
 pub use space::Space;
 pub use spatial_hash_grid::SpatialHashGrid;
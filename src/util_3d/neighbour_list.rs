@@ -0,0 +1,78 @@
+use std::ops::Range;
+
+use crate::kernel::Kernel;
+use crate::real::Real;
+
+use super::{ParticleSoa, SpatialHashGrid};
+
+/// Neighbour indices of every particle in a `ParticleSoa`, stored back to back.
+///
+/// A particle is counted as its own neighbour, the same as `Space::neighbour`.
+#[derive(Debug, Default, Clone)]
+pub struct NeighbourList {
+    offset: Vec<Range<usize>>,
+    index: Vec<usize>,
+}
+
+impl NeighbourList {
+    /// A pair is listed when it is inside the support radius of either particle,
+    /// so `b` is a neighbour of `a` exactly when `a` is one of `b`.
+    pub fn new<T: Kernel>(soa: &ParticleSoa) -> Self {
+        let support_radius = soa
+            .kernel_radius
+            .iter()
+            .map(|&h| T::new(h).support_radius())
+            .collect::<Vec<_>>();
+        let grid_size = support_radius.iter().cloned().fold(0., Real::max);
+
+        let mut grid = SpatialHashGrid::new(grid_size);
+        grid.update(&soa.position);
+
+        let mut obj = Self {
+            offset: Vec::with_capacity(soa.len()),
+            index: vec![],
+        };
+        for (a, &radius) in support_radius.iter().enumerate() {
+            let begin = obj.index.len();
+            let position = soa.position[a];
+            obj.index.extend(grid.lookup(&position, grid_size).filter(|&&b| {
+                position.distance(soa.position[b]) <= radius.max(support_radius[b])
+            }));
+            obj.index[begin..].sort_unstable();
+            obj.offset.push(begin..obj.index.len());
+        }
+        obj
+    }
+
+    pub fn of(&self, i: usize) -> &[usize] {
+        &self.index[self.offset[i].clone()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::init_setup;
+    use super::*;
+    use crate::kernel::CubicSpline;
+
+    #[test]
+    fn match_brute_force() {
+        let h = 0.6;
+        let mut soa = ParticleSoa::from(init_setup::random_points(500, -3., 3., 1., h));
+        soa.kernel_radius
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, h)| *h *= 1. + (i % 3) as Real * 0.5);
+        let neighbours = NeighbourList::new::<CubicSpline>(&soa);
+        let radius = |i: usize| CubicSpline::new(soa.kernel_radius[i]).support_radius();
+
+        for a in 0..soa.len() {
+            let expect = (0..soa.len())
+                .filter(|&b| {
+                    soa.position[a].distance(soa.position[b]) <= radius(a).max(radius(b))
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(neighbours.of(a), expect.as_slice());
+        }
+    }
+}
@@ -1,19 +1,67 @@
 use std::iter::Sum;
 
-use macroquad::math::Vec3;
+use crate::real::{Real, Vector};
 
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Particle {
-    pub position: Vec3,
-    pub velocity: Vec3,
-    pub mass: f32,
-    pub kernel_radius: f32,
-    pub density: f32,
-    pub pressure: f32,
+    /// Index of the particle in its `Space`, stays the same when the space is rehashed.
+    pub id: usize,
+    pub position: Vector,
+    pub velocity: Vector,
+    pub mass: Real,
+    pub kernel_radius: Real,
+    pub density: Real,
+    pub pressure: Real,
+    /// grad-h correction factor, one unless the kernel radius follows the density exactly.
+    pub omega: Real,
+    /// Apparent dynamic viscosity, zero without a physical viscosity model.
+    pub viscosity: Real,
+    /// Artificial viscosity coefficient, only used when it is switched per particle.
+    pub alpha: Real,
+    /// `div v` when `alpha` was last updated.
+    pub velocity_divergence: Real,
+    /// Balsara 1995 factor the artificial viscosity is scaled with, one without the limiter.
+    pub balsara: Real,
+    /// `curl v`.
+    pub vorticity: Vector,
+    /// Index into the material table of the simulation, zero for a single fluid.
+    pub material: usize,
+    /// In degrees Celsius, room temperature unless the scene sets it.
+    pub temperature: Real,
+    /// Share of the latent heat of melting the particle holds, one for a fluid.
+    pub liquid_fraction: Real,
+}
+
+impl Default for Particle {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            position: Vector::ZERO,
+            velocity: Vector::ZERO,
+            mass: 0.,
+            kernel_radius: 0.,
+            density: 0.,
+            pressure: 0.,
+            omega: 1.,
+            viscosity: 0.,
+            alpha: 0.,
+            velocity_divergence: 0.,
+            balsara: 1.,
+            vorticity: Vector::ZERO,
+            material: 0,
+            temperature: 20.,
+            liquid_fraction: 1.,
+        }
+    }
 }
 
 impl Particle {
-    pub fn new(position: Vec3, velocity: Vec3, mass: f32, kernel_radius: f32) -> Self {
+    /// Frozen particles keep still and only hold the fluid back.
+    pub fn is_solid(&self) -> bool {
+        self.liquid_fraction < 0.5
+    }
+
+    pub fn new(position: Vector, velocity: Vector, mass: Real, kernel_radius: Real) -> Self {
         Self {
             position,
             velocity,
@@ -24,8 +72,8 @@ impl Particle {
     }
 }
 
-impl From<Vec3> for Particle {
-    fn from(value: Vec3) -> Self {
+impl From<Vector> for Particle {
+    fn from(value: Vector) -> Self {
         Self {
             position: value,
             ..Default::default()
@@ -33,8 +81,8 @@ impl From<Vec3> for Particle {
     }
 }
 
-impl From<(Vec3, Vec3)> for Particle {
-    fn from(value: (Vec3, Vec3)) -> Self {
+impl From<(Vector, Vector)> for Particle {
+    fn from(value: (Vector, Vector)) -> Self {
         Self {
             position: value.0,
             velocity: value.1,
@@ -42,8 +90,8 @@ impl From<(Vec3, Vec3)> for Particle {
         }
     }
 }
-impl From<(Vec3, Vec3, f32)> for Particle {
-    fn from(value: (Vec3, Vec3, f32)) -> Self {
+impl From<(Vector, Vector, Real)> for Particle {
+    fn from(value: (Vector, Vector, Real)) -> Self {
         Self {
             position: value.0,
             velocity: value.1,
@@ -53,8 +101,8 @@ impl From<(Vec3, Vec3, f32)> for Particle {
     }
 }
 
-impl From<(Vec3, Vec3, f32, f32)> for Particle {
-    fn from(value: (Vec3, Vec3, f32, f32)) -> Self {
+impl From<(Vector, Vector, Real, Real)> for Particle {
+    fn from(value: (Vector, Vector, Real, Real)) -> Self {
         Self {
             position: value.0,
             velocity: value.1,
@@ -0,0 +1,185 @@
+use crate::real::{Real, Vector};
+
+use super::Particle;
+
+/// Structure-of-arrays storage of particles, index `i` in every array belongs to particle `i`.
+///
+/// Only a snapshot, from `Space::to_soa`, that the `*_soa` and `*_simd` paths of the models
+/// read with a `NeighbourList` when the scene picks that pair loop. The simulator keeps its
+/// particles in `Space`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParticleSoa {
+    pub id: Vec<usize>,
+    pub position: Vec<Vector>,
+    pub velocity: Vec<Vector>,
+    pub mass: Vec<Real>,
+    pub kernel_radius: Vec<Real>,
+    pub density: Vec<Real>,
+    pub pressure: Vec<Real>,
+    pub omega: Vec<Real>,
+    pub viscosity: Vec<Real>,
+    pub alpha: Vec<Real>,
+    pub velocity_divergence: Vec<Real>,
+    pub balsara: Vec<Real>,
+    pub vorticity: Vec<Vector>,
+    pub material: Vec<usize>,
+    pub temperature: Vec<Real>,
+    pub liquid_fraction: Vec<Real>,
+}
+
+impl ParticleSoa {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            id: Vec::with_capacity(capacity),
+            position: Vec::with_capacity(capacity),
+            velocity: Vec::with_capacity(capacity),
+            mass: Vec::with_capacity(capacity),
+            kernel_radius: Vec::with_capacity(capacity),
+            density: Vec::with_capacity(capacity),
+            pressure: Vec::with_capacity(capacity),
+            omega: Vec::with_capacity(capacity),
+            viscosity: Vec::with_capacity(capacity),
+            alpha: Vec::with_capacity(capacity),
+            velocity_divergence: Vec::with_capacity(capacity),
+            balsara: Vec::with_capacity(capacity),
+            vorticity: Vec::with_capacity(capacity),
+            material: Vec::with_capacity(capacity),
+            temperature: Vec::with_capacity(capacity),
+            liquid_fraction: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.position.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position.is_empty()
+    }
+
+    pub fn push(&mut self, particle: Particle) {
+        self.id.push(particle.id);
+        self.position.push(particle.position);
+        self.velocity.push(particle.velocity);
+        self.mass.push(particle.mass);
+        self.kernel_radius.push(particle.kernel_radius);
+        self.density.push(particle.density);
+        self.pressure.push(particle.pressure);
+        self.omega.push(particle.omega);
+        self.viscosity.push(particle.viscosity);
+        self.alpha.push(particle.alpha);
+        self.velocity_divergence.push(particle.velocity_divergence);
+        self.balsara.push(particle.balsara);
+        self.vorticity.push(particle.vorticity);
+        self.material.push(particle.material);
+        self.temperature.push(particle.temperature);
+        self.liquid_fraction.push(particle.liquid_fraction);
+    }
+
+    pub fn get(&self, index: usize) -> Particle {
+        Particle {
+            id: self.id[index],
+            position: self.position[index],
+            velocity: self.velocity[index],
+            mass: self.mass[index],
+            kernel_radius: self.kernel_radius[index],
+            density: self.density[index],
+            pressure: self.pressure[index],
+            omega: self.omega[index],
+            viscosity: self.viscosity[index],
+            alpha: self.alpha[index],
+            velocity_divergence: self.velocity_divergence[index],
+            balsara: self.balsara[index],
+            vorticity: self.vorticity[index],
+            material: self.material[index],
+            temperature: self.temperature[index],
+            liquid_fraction: self.liquid_fraction[index],
+        }
+    }
+
+    pub fn set(&mut self, index: usize, particle: &Particle) {
+        self.id[index] = particle.id;
+        self.position[index] = particle.position;
+        self.velocity[index] = particle.velocity;
+        self.mass[index] = particle.mass;
+        self.kernel_radius[index] = particle.kernel_radius;
+        self.density[index] = particle.density;
+        self.pressure[index] = particle.pressure;
+        self.omega[index] = particle.omega;
+        self.viscosity[index] = particle.viscosity;
+        self.alpha[index] = particle.alpha;
+        self.velocity_divergence[index] = particle.velocity_divergence;
+        self.balsara[index] = particle.balsara;
+        self.vorticity[index] = particle.vorticity;
+        self.material[index] = particle.material;
+        self.temperature[index] = particle.temperature;
+        self.liquid_fraction[index] = particle.liquid_fraction;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Particle> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+}
+
+impl FromIterator<Particle> for ParticleSoa {
+    fn from_iter<I: IntoIterator<Item = Particle>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut soa = Self::with_capacity(iter.size_hint().0);
+        iter.for_each(|p| soa.push(p));
+        soa
+    }
+}
+
+impl<'a> FromIterator<&'a Particle> for ParticleSoa {
+    fn from_iter<I: IntoIterator<Item = &'a Particle>>(iter: I) -> Self {
+        iter.into_iter().cloned().collect()
+    }
+}
+
+impl From<Vec<Particle>> for ParticleSoa {
+    fn from(value: Vec<Particle>) -> Self {
+        value.into_iter().collect()
+    }
+}
+
+impl From<ParticleSoa> for Vec<Particle> {
+    fn from(value: ParticleSoa) -> Self {
+        value.iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::init_setup;
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut particles = init_setup::create_cube(1., 3, Vector::ZERO, 2., 1.3);
+        particles.iter_mut().enumerate().for_each(|(i, p)| {
+            p.velocity = Vector::splat(i as Real);
+            p.density = i as Real * 0.5;
+            p.pressure = -(i as Real);
+            p.omega = 1. / (i + 1) as Real;
+            p.viscosity = i as Real * 0.1;
+            p.alpha = i as Real * 0.01;
+            p.velocity_divergence = -(i as Real) * 0.2;
+            p.balsara = 1. / (i + 2) as Real;
+            p.vorticity = Vector::Z * i as Real;
+            p.material = i % 2;
+            p.temperature = i as Real * 3.;
+            p.liquid_fraction = 1. / (i + 1) as Real;
+        });
+
+        let soa = ParticleSoa::from(particles.clone());
+        assert_eq!(soa.len(), particles.len());
+        for (i, p) in particles.iter().enumerate() {
+            assert_eq!(soa.position[i], p.position);
+            assert_eq!(soa.density[i], p.density);
+            assert_eq!(&soa.get(i), p);
+        }
+
+        let back: Vec<Particle> = soa.into();
+        assert_eq!(back, particles);
+    }
+}
@@ -3,42 +3,48 @@ use rayon::prelude::*;
 use std::{collections::HashMap, iter, ops::Deref};
 
 use crate::kernel;
+use crate::profiler;
+use crate::real::Real;
 
-use super::Particle;
+use super::{Particle, ParticleSoa};
 
 type Key = [i32; 3];
 
 #[derive(Debug, Default)]
 pub struct Space {
-    grid_size: f32,
+    grid_size: Real,
     table: HashMap<Key, Vec<Particle>>,
     update_count: usize,
+    count: usize,
+    active: Option<Vec<bool>>,
 }
 
 #[inline]
-fn hash(grid_size: f32, particle: &Particle) -> Key {
+fn hash(grid_size: Real, particle: &Particle) -> Key {
     (particle.position / grid_size).as_ivec3().to_array()
 }
 
 impl Space {
-    pub fn new(grid_size: f32, particles: Vec<Particle>) -> Self {
+    /// Particle ids are reassigned to their index in `particles`.
+    pub fn new(grid_size: Real, particles: Vec<Particle>) -> Self {
         let mut obj = Self {
             grid_size,
+            count: particles.len(),
             ..Default::default()
         };
         obj.table = particles
             .into_iter()
+            .enumerate()
+            .map(|(id, p)| Particle { id, ..p })
             .into_group_map_by(|v| hash(grid_size, v));
         obj
     }
 
     #[inline]
     pub fn add_one(&mut self, particle: Particle) {
-        {
-            let this = &mut *self;
-            let key = hash(this.grid_size, &particle);
-            this.table.entry(key).or_default().push(particle)
-        };
+        let id = self.count;
+        self.count += 1;
+        self.insert(Particle { id, ..particle });
     }
 
     #[inline]
@@ -46,7 +52,84 @@ impl Space {
         particles.into_iter().for_each(|p| self.add_one(p))
     }
 
+    #[inline]
+    fn insert(&mut self, particle: Particle) {
+        let key = hash(self.grid_size, &particle);
+        self.table.entry(key).or_default().push(particle)
+    }
+
+    /// Number of particles, ids run from 0 to `len() - 1`.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// `value` of every particle, indexed by id.
+    pub fn collect_by_id<T: Clone + Default>(&self, value: impl Fn(&Particle) -> T) -> Vec<T> {
+        let mut ret = vec![T::default(); self.count];
+        self.particles().for_each(|p| ret[p.id] = value(p));
+        ret
+    }
+
+    /// Largest kernel radius, search with it to find every particle whose support covers another.
+    pub fn max_kernel_radius(&self) -> Real {
+        self.particles()
+            .map(|p| p.kernel_radius)
+            .fold(0., Real::max)
+    }
+
+    /// Restrict `active_particles` to the ids set in `active`, `None` makes every particle active.
+    pub fn set_active(&mut self, active: Option<Vec<bool>>) {
+        debug_assert!(active.as_ref().is_none_or(|a| a.len() == self.count));
+        self.active = active;
+    }
+
+    #[inline]
+    pub fn is_active(&self, particle: &Particle) -> bool {
+        self.active.as_ref().is_none_or(|a| a[particle.id])
+    }
+
+    /// Particles the models update this step, same order as `particles()` without the inactive ones.
+    pub fn active_particles(&self) -> impl Iterator<Item = &Particle> {
+        self.particles().filter(|p| self.is_active(p))
+    }
+
+    /// `active_particles()` spread over the rayon pool, collecting keeps the same order.
+    pub fn par_active_particles(&self) -> impl IndexedParallelIterator<Item = &Particle> {
+        self.active_particles().collect::<Vec<_>>().into_par_iter()
+    }
+
+    pub fn active_particles_mut(&mut self) -> impl Iterator<Item = &mut Particle> {
+        let active = &self.active;
+        self.table
+            .values_mut()
+            .flatten()
+            .filter(move |p| active.as_ref().is_none_or(|a| a[p.id]))
+    }
+
+    /// Pick the entries of the active particles out of `values`, indexed by id, in the order of
+    /// `active_particles()`.
+    pub fn active_by_id<T: Clone>(&self, values: &[T]) -> Vec<T> {
+        self.active_particles()
+            .map(|p| values[p.id].clone())
+            .collect()
+    }
+
+    /// Reorder `values`, given in the order of `active_particles()`, to be indexed by id.
+    /// Inactive particles get `T::default()`.
+    pub fn order_by_id<T: Clone + Default>(&self, values: Vec<T>) -> Vec<T> {
+        let mut ret = vec![T::default(); self.count];
+        self.active_particles()
+            .zip(values)
+            .for_each(|(p, v)| ret[p.id] = v);
+        ret
+    }
+
     pub fn update(&mut self) {
+        let _scope = profiler::scope("Space::update");
         let mut dropped = vec![];
         self.table.iter_mut().for_each(|(key, val)| {
             let (stay, mut drop): (Vec<_>, Vec<_>) =
@@ -55,7 +138,7 @@ impl Space {
             dropped.append(&mut drop);
         });
 
-        self.add_bulk(dropped);
+        dropped.into_iter().for_each(|p| self.insert(p));
 
         self.update_count += 1;
         if self.update_count == 100 {
@@ -82,10 +165,23 @@ impl Space {
             .flat_map(|(_, v)| v.par_iter_mut())
     }
 
+    /// Snapshot of every particle, index `i` holds the particle with id `i`.
+    pub fn to_soa(&self) -> ParticleSoa {
+        let mut particles = self.particles().collect::<Vec<_>>();
+        particles.sort_unstable_by_key(|p| p.id);
+        particles.into_iter().collect()
+    }
+
+    /// Write a snapshot from `to_soa` back, call `update` afterward if positions changed.
+    pub fn copy_from_soa(&mut self, soa: &ParticleSoa) {
+        assert_eq!(self.count, soa.len());
+        self.particles_mut().for_each(|p| *p = soa.get(p.id));
+    }
+
     pub fn neighbour(
         &self,
         particle: &Particle,
-        radius: f32,
+        radius: Real,
     ) -> impl Iterator<Item = &Particle> + Clone {
         let key = hash(self.grid_size, particle);
         self.neighbour_by_key(&key, radius)
@@ -94,13 +190,13 @@ impl Space {
     pub fn par_neighbour(
         &self,
         particle: &Particle,
-        radius: f32,
+        radius: Real,
     ) -> impl ParallelIterator<Item = &Particle> + Clone {
         let key = hash(self.grid_size, particle);
         self.par_neighbour_by_key(&key, radius)
     }
 
-    fn neighbour_by_key(&self, key: &Key, radius: f32) -> impl Iterator<Item = &Particle> + Clone {
+    fn neighbour_by_key(&self, key: &Key, radius: Real) -> impl Iterator<Item = &Particle> + Clone {
         let r: i32 = (radius / self.grid_size).ceil() as i32;
         let x = key[0] - r..=key[0] + r;
         let y = key[1] - r..=key[1] + r;
@@ -114,7 +210,7 @@ impl Space {
     fn par_neighbour_by_key(
         &self,
         key: &Key,
-        radius: f32,
+        radius: Real,
     ) -> impl ParallelIterator<Item = &Particle> + Clone {
         let r: i32 = (radius / self.grid_size).ceil() as i32;
         let x = key[0] - r..=key[0] + r;
@@ -155,4 +251,20 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn soa_by_id() {
+        let particles = init_setup::random_points(200, -5., 5., 1., 1.);
+        let mut grid = Space::new(1., particles);
+        let mut soa = grid.to_soa();
+        assert_eq!(soa.id, (0..grid.len()).collect_vec());
+
+        soa.density
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, d)| *d = i as Real);
+        grid.copy_from_soa(&soa);
+        grid.particles()
+            .for_each(|p| assert_eq!(p.density, p.id as Real));
+    }
 }
@@ -1,31 +1,31 @@
 use std::{collections::HashMap, ops::Range};
 
 use itertools::iproduct;
-use macroquad::prelude::*;
+use crate::real::{Real, Vector};
 
 type Key = [i32; 3];
 
 #[derive(Debug, Default)]
 pub struct SpatialHashGrid {
-    grid_size: f32,
+    grid_size: Real,
     table: Vec<usize>,
     info: HashMap<Key, Range<usize>>,
 }
 
 impl SpatialHashGrid {
-    pub fn new(grid_size: f32) -> Self {
+    pub fn new(grid_size: Real) -> Self {
         Self {
             grid_size,
             ..Default::default()
         }
     }
 
-    fn hash(&self, pos: Vec3) -> Key {
+    fn hash(&self, pos: Vector) -> Key {
         let x = (pos / self.grid_size).ceil().to_array();
         [x[0] as i32, x[1] as i32, x[2] as i32]
     }
 
-    pub fn update(&mut self, position: &Vec<Vec3>) {
+    pub fn update(&mut self, position: &Vec<Vector>) {
         let mut tmp: HashMap<Key, Vec<usize>> = Default::default();
         for (i, pos) in position.iter().enumerate() {
             let hash = self.hash(*pos);
@@ -44,7 +44,7 @@ impl SpatialHashGrid {
         self.table = tmp.into_values().flatten().collect();
     }
 
-    pub fn lookup(&self, position: &Vec3, support_radius: f32) -> impl Iterator<Item = &usize> {
+    pub fn lookup(&self, position: &Vector, support_radius: Real) -> impl Iterator<Item = &usize> {
         let r: i32 = (support_radius / self.grid_size).ceil() as i32;
         let key = self.hash(*position);
         let x = key[0] - r..=key[0] + r;
@@ -65,9 +65,9 @@ mod tests {
     use macroquad::rand::gen_range;
     use std::collections::HashSet;
 
-    fn generate_points(count: usize) -> Vec<Vec3> {
+    fn generate_points(count: usize) -> Vec<Vector> {
         let random_pos =
-            || Vec3::from_array([gen_range(-5., 5.), gen_range(-5., 5.), gen_range(-5., 5.)]);
+            || Vector::from_array([gen_range(-5., 5.), gen_range(-5., 5.), gen_range(-5., 5.)]);
         let mut position = vec![];
         for _ in 0..count {
             position.push(random_pos())
@@ -83,7 +83,7 @@ async fn main() {
     let density_model = Density::new(cubic_spline, mass);
     let pressure_model = pressure::Tait::new(cubic_spline, mass, rest_density, 7, speed_of_sound);
     let viscoity_model = viscosity::Artificial::new(cubic_spline, mass, speed_of_sound);
-    let surface_tension_model = surface_tension::BeakerTeschner07::new(cubic_spline, mass);
+    let surface_tension_model = surface_tension::BeckerTeschner07::new(cubic_spline, mass);
 
     let mut grid = SpatialHashGrid::new(kernel_radius);
 